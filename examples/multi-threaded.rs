@@ -1,5 +1,4 @@
 extern crate futures;
-extern crate tokio_core;
 extern crate tk_easyloop;
 
 use std::net::{TcpListener as StdListener, SocketAddr};
@@ -7,8 +6,7 @@ use std::thread;
 use std::env;
 
 use futures::stream::Stream;
-use tokio_core::net::TcpListener;
-use tk_easyloop::{run, handle};
+use tk_easyloop::{run, tcp_from_std};
 
 
 fn main() {
@@ -21,7 +19,7 @@ fn main() {
         let lst = socket.try_clone().expect("cloning bind socket");
         threads.push(thread::spawn(move || {
             run(|| {
-                TcpListener::from_listener(lst, &addr, &handle())
+                tcp_from_std(lst)
                 .expect("listener created")
                 .incoming()
                 .for_each(|(_sock, addr)| {
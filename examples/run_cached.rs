@@ -0,0 +1,30 @@
+extern crate tk_easyloop;
+
+use std::env;
+use std::io;
+use std::time::Instant;
+use tk_easyloop::{run, run_cached, drop_cached_core};
+
+fn main() {
+    let iterations = env::args().nth(1)
+        .map(|x| x.parse().expect("number of iterations"))
+        .unwrap_or(10000);
+
+    let start = Instant::now();
+    for _ in 0..iterations {
+        run_cached(|| Ok::<_, io::Error>(())).unwrap();
+    }
+    println!("run_cached: {:?} per iteration",
+        start.elapsed() / iterations);
+    drop_cached_core();
+
+    // creating a reactor is both slow and holds file descriptors for some
+    // time after it's dropped, so use a lower number of iterations here
+    let iterations = iterations / 100 + 1;
+    let start = Instant::now();
+    for _ in 0..iterations {
+        run(|| Ok::<_, io::Error>(())).unwrap();
+    }
+    println!("run:        {:?} per iteration",
+        start.elapsed() / iterations);
+}
@@ -1,23 +1,19 @@
-extern crate futures;
 extern crate tk_easyloop;
 
 use std::time::Duration;
-use futures::{Stream, Future};
-use tk_easyloop::{run_forever, interval, spawn};
+use tk_easyloop::{run_forever, interval, spawn_stream};
 
 fn main() {
 
     run_forever(|| {
-        spawn(interval(Duration::new(1, 0))
-            .for_each(|()| {
-                println!("1 sec interval");
-                Ok(())
-            }).map_err(|_| ()));
-        spawn(interval(Duration::from_millis(500))
-            .for_each(|()| {
-                println!("Half second interval");
-                Ok(())
-            }).map_err(|_| ()));
+        spawn_stream(interval(Duration::new(1, 0)), |()| {
+            println!("1 sec interval");
+            Ok(())
+        });
+        spawn_stream(interval(Duration::from_millis(500)), |()| {
+            println!("Half second interval");
+            Ok(())
+        });
         Ok::<_, ()>(())
     }).unwrap();
 }
@@ -0,0 +1,152 @@
+use std::cell::RefCell;
+
+use futures::{Future, Async, Poll};
+use futures::sync::oneshot;
+
+use spawn;
+
+
+/// A handle returned by `spawn_abortable()` which allows to stop the task
+///
+/// By default dropping the handle doesn't stop the task, use
+/// `abort_on_drop(true)` to stop it on drop. The handle can be sent to
+/// another thread.
+pub struct AbortHandle {
+    sender: RefCell<Option<oneshot::Sender<()>>>,
+    abort_on_drop: bool,
+}
+
+/// A guard returned by `scope()` which aborts all its tasks when dropped
+pub struct TaskScope {
+    tasks: RefCell<Vec<AbortHandle>>,
+}
+
+struct Abortable<F> {
+    future: F,
+    abort: Option<oneshot::Receiver<()>>,
+}
+
+/// Spawn a future to the current main loop and return a handle to abort it
+///
+/// This is useful for tasks tied to the lifetime of some resource, e.g. a
+/// background worker of a connection:
+///
+/// ```ignore
+/// struct Connection {
+///     keepalive: AbortHandle,
+/// }
+/// let keepalive = spawn_abortable(send_pings(sink)).abort_on_drop(true);
+/// ```
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn spawn_abortable<F>(f: F) -> AbortHandle
+    where F: Future<Item=(), Error=()> + 'static
+{
+    let (tx, rx) = oneshot::channel();
+    spawn(Abortable { future: f, abort: Some(rx) });
+    AbortHandle {
+        sender: RefCell::new(Some(tx)),
+        abort_on_drop: false,
+    }
+}
+
+/// Create a guard which owns a set of background tasks
+///
+/// Tasks spawned by `TaskScope::spawn()` are aborted when the guard is
+/// dropped, so a function (or a structure) can own background tasks
+/// without tracking an `AbortHandle` for each of them:
+///
+/// ```ignore
+/// fn serve(conn: Connection) -> Box<Future<Item=(), Error=()>> {
+///     let tasks = scope();
+///     tasks.spawn(send_pings(conn.sink.clone()));
+///     tasks.spawn(report_stats(conn.stats.clone()));
+///     Box::new(conn.process().then(move |res| {
+///         drop(tasks);
+///         res
+///     }))
+/// }
+/// ```
+///
+/// Note: like with `AbortHandle::abort()` the tasks are not dropped
+/// synchronously, they end at the next loop iteration.
+pub fn scope() -> TaskScope {
+    TaskScope { tasks: RefCell::new(Vec::new()) }
+}
+
+impl TaskScope {
+    /// Spawn a future which is aborted when the scope is dropped
+    ///
+    /// # Panics
+    ///
+    /// This function panics if there is no currently running loop (i.e.
+    /// this function is not running from the inside of `run()`.
+    pub fn spawn<F>(&self, f: F)
+        where F: Future<Item=(), Error=()> + 'static
+    {
+        let mut tasks = self.tasks.borrow_mut();
+        tasks.retain(|task| !task.is_finished());
+        tasks.push(spawn_abortable(f).abort_on_drop(true));
+    }
+    /// Returns number of tasks in the scope that are not finished yet
+    pub fn len(&self) -> usize {
+        self.tasks.borrow().iter().filter(|t| !t.is_finished()).count()
+    }
+    /// Returns `true` if all the tasks in the scope are finished
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl AbortHandle {
+    /// Stop the task
+    ///
+    /// The future is dropped at the next loop iteration (unless it's
+    /// already finished).
+    pub fn abort(&self) {
+        if let Some(tx) = self.sender.borrow_mut().take() {
+            tx.send(()).ok();
+        }
+    }
+    /// Whether to abort the task when the handle is dropped
+    pub fn abort_on_drop(mut self, value: bool) -> AbortHandle {
+        self.abort_on_drop = value;
+        self
+    }
+    /// Returns `true` if the task is finished, or aborted
+    pub fn is_finished(&self) -> bool {
+        self.sender.borrow().as_ref().map(|tx| tx.is_canceled())
+            .unwrap_or(true)
+    }
+}
+
+impl Drop for AbortHandle {
+    fn drop(&mut self) {
+        if self.abort_on_drop {
+            self.abort();
+        }
+    }
+}
+
+impl<F: Future<Item=(), Error=()>> Future for Abortable<F> {
+    type Item = ();
+    type Error = ();
+    fn poll(&mut self) -> Poll<(), ()> {
+        let aborted = match self.abort.as_mut().map(|rx| rx.poll()) {
+            Some(Ok(Async::Ready(()))) => true,
+            // handle is dropped without aborting
+            Some(Err(_)) => {
+                self.abort = None;
+                false
+            }
+            Some(Ok(Async::NotReady)) | None => false,
+        };
+        if aborted {
+            return Ok(Async::Ready(()));
+        }
+        self.future.poll()
+    }
+}
@@ -0,0 +1,72 @@
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+use now;
+
+
+thread_local! {
+    static CURRENT: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+struct Restore(Option<Instant>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        CURRENT.with(|c| c.set(self.0));
+    }
+}
+
+/// Run a function with the current deadline set
+///
+/// This allows a request handler to establish "everything under here must
+/// finish by `at`" without passing deadline through all the layers:
+///
+/// ```ignore
+/// with_deadline(now() + Duration::new(5, 0), || {
+///     handle_request(req)
+/// })
+/// // deeper in the call stack
+/// let dur = clamp_to_deadline(Duration::new(30, 0));
+/// with_timeout(fetch_from_backend(), dur)
+/// ```
+///
+/// Nested calls can only make the deadline earlier. Note that the deadline
+/// is set only for the time the function runs, it's not attached to the
+/// futures created by the function (so must be read when creating them).
+pub fn with_deadline<F, R>(at: Instant, f: F) -> R
+    where F: FnOnce() -> R
+{
+    let prev = CURRENT.with(|c| c.get());
+    let _restore = Restore(prev);
+    let at = prev.map(|prev| prev.min(at)).unwrap_or(at);
+    CURRENT.with(|c| c.set(Some(at)));
+    f()
+}
+
+/// Returns the deadline set by `with_deadline()`
+pub fn current_deadline() -> Option<Instant> {
+    CURRENT.with(|c| c.get())
+}
+
+/// Returns time left until the deadline set by `with_deadline()`
+///
+/// Returns zero if the deadline has already passed, and `None` if there
+/// is no deadline.
+pub fn deadline_remaining() -> Option<Duration> {
+    current_deadline().map(|at| at.saturating_duration_since(now()))
+}
+
+/// Returns the duration limited by the deadline set by `with_deadline()`
+///
+/// Timer functions don't respect the deadline by themselves, this is an
+/// opt-in to use for timeouts:
+///
+/// ```ignore
+/// timeout(clamp_to_deadline(Duration::new(30, 0)))
+/// ```
+pub fn clamp_to_deadline(dur: Duration) -> Duration {
+    match deadline_remaining() {
+        Some(left) => dur.min(left),
+        None => dur,
+    }
+}
@@ -0,0 +1,61 @@
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::sync::mpsc;
+use std::thread;
+
+use futures::sync::oneshot;
+
+
+type Job = Box<dyn FnOnce() + Send>;
+
+static POOL: OnceLock<Mutex<mpsc::Sender<Job>>> = OnceLock::new();
+
+fn start_pool() -> Mutex<mpsc::Sender<Job>> {
+    let (tx, rx) = mpsc::channel::<Job>();
+    let rx = Arc::new(Mutex::new(rx));
+    let threads = thread::available_parallelism().map(|n| n.get())
+        .unwrap_or(4);
+    for idx in 0..threads {
+        let rx = rx.clone();
+        thread::Builder::new()
+            .name(format!("tk-easyloop-blocking-{}", idx))
+            .spawn(move || loop {
+                let job = match rx.lock().unwrap().recv() {
+                    Ok(job) => job,
+                    Err(_) => return,
+                };
+                // panic drops the result sender, so the receiver gets
+                // `Canceled`, and the worker thread is kept alive
+                catch_unwind(AssertUnwindSafe(job)).ok();
+            })
+            .expect("spawn blocking thread");
+    }
+    Mutex::new(tx)
+}
+
+/// Run a blocking function in a thread pool
+///
+/// Returns a receiver which is resolved with the result of the function,
+/// so it can be used on the loop without blocking it:
+///
+/// ```ignore
+/// spawn_blocking(|| fs::read("config.yaml"))
+/// .map_err(|_| panic!("config reader panicked"))
+/// .and_then(|data| data)
+/// ```
+///
+/// The pool is started on the first call and has a thread per CPU. If the
+/// function panics the receiver fails with `Canceled`. This function
+/// doesn't require a running loop.
+pub fn spawn_blocking<F, T>(f: F) -> oneshot::Receiver<T>
+    where F: FnOnce() -> T + Send + 'static,
+          T: Send + 'static,
+{
+    let (tx, rx) = oneshot::channel();
+    let job: Job = Box::new(move || {
+        tx.send(f()).ok();
+    });
+    POOL.get_or_init(start_pool).lock().unwrap()
+        .send(job).expect("blocking pool is alive");
+    rx
+}
@@ -0,0 +1,73 @@
+use std::cell::Cell;
+
+use futures::{Future, Poll};
+
+use spawn;
+
+
+thread_local! {
+    static LIMIT: Cell<usize> = const { Cell::new(usize::MAX) };
+    static ACTIVE: Cell<usize> = const { Cell::new(0) };
+}
+
+struct Permit<F> {
+    future: F,
+}
+
+impl<F: Future> Future for Permit<F> {
+    type Item = F::Item;
+    type Error = F::Error;
+    fn poll(&mut self) -> Poll<F::Item, F::Error> {
+        self.future.poll()
+    }
+}
+
+impl<F> Drop for Permit<F> {
+    fn drop(&mut self) {
+        ACTIVE.try_with(|a| a.set(a.get() - 1)).ok();
+    }
+}
+
+/// Set the limit of concurrently running futures spawned by
+/// `spawn_bounded()` in the current thread
+///
+/// By default there is no limit. Lowering the limit doesn't affect already
+/// running futures.
+pub fn set_spawn_limit(limit: usize) {
+    LIMIT.with(|l| l.set(limit));
+}
+
+/// Returns the limit set by `set_spawn_limit()`
+pub(crate) fn spawn_limit() -> usize {
+    LIMIT.with(|l| l.get())
+}
+
+/// Spawn a future to the current main loop unless the limit is reached
+///
+/// When there are already `set_spawn_limit()` futures running, the future
+/// is returned back as an error, so the caller can apply backpressure
+/// (e.g. stop accepting connections until some of them are finished).
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn spawn_bounded<F>(f: F) -> Result<(), F>
+    where F: Future<Item=(), Error=()> + 'static
+{
+    let limit = LIMIT.with(|l| l.get());
+    let allowed = ACTIVE.with(|a| {
+        if a.get() < limit {
+            a.set(a.get() + 1);
+            true
+        } else {
+            false
+        }
+    });
+    if allowed {
+        spawn(Permit { future: f });
+        Ok(())
+    } else {
+        Err(f)
+    }
+}
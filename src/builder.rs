@@ -0,0 +1,82 @@
+use futures::IntoFuture;
+
+use driver::Config;
+use {run_with_config, forever_with_config};
+
+
+/// A builder for the loop with non-default settings
+///
+/// ```ignore
+/// let worker = Loop::new().name("worker").catch_panics(true).build();
+/// worker.run_forever(|| {
+///     spawn(some_task());
+///     Ok::<(), ()>(())
+/// })
+/// ```
+///
+/// Loop still sets the thread-local handle, so all the free functions of
+/// this crate work inside.
+#[derive(Default)]
+pub struct Loop {
+    config: Config,
+}
+
+/// A configured loop created by `Loop::build()`
+///
+/// The same runner can be used to run the loop multiple times (a new
+/// `Core` is created for each run).
+pub struct Runner {
+    config: Config,
+}
+
+impl Loop {
+    /// Create a builder with default settings
+    pub fn new() -> Loop {
+        Loop::default()
+    }
+    /// Set the name of the loop
+    ///
+    /// See `run_named()` for what names are used for.
+    pub fn name(&mut self, name: &'static str) -> &mut Self {
+        self.config.name = Some(name);
+        self
+    }
+    /// Catch panics of all the futures spawned by `spawn()` and `spawn_fn()`
+    ///
+    /// Panics are reported the same way as in `spawn_catch()`. Note: futures
+    /// aren't required to be `UnwindSafe` in this case.
+    pub fn catch_panics(&mut self, value: bool) -> &mut Self {
+        self.config.catch_panics = value;
+        self
+    }
+    /// Create a runner with the configured settings
+    pub fn build(&mut self) -> Runner {
+        Runner {
+            config: self.config.clone(),
+        }
+    }
+}
+
+impl Runner {
+    /// Run the main loop and initialize it by running a function
+    ///
+    /// This is the same as `tk_easyloop::run()` but with the loop settings
+    /// applied.
+    pub fn run<F, R>(&self, f: F) -> Result<R::Item, R::Error>
+        where F: FnOnce() -> R,
+              R: IntoFuture,
+    {
+        run_with_config(&self.config, f)
+    }
+    /// Run the main loop and initialize it by running a function, which
+    /// spawns more futures in the main loop. Then run loop indefinitely
+    /// (or until `shutdown()` is called).
+    ///
+    /// This is the same as `tk_easyloop::run_forever()` but with the loop
+    /// settings applied.
+    pub fn run_forever<F, E>(&self, f: F) -> Result<(), E>
+        where F: FnOnce() -> Result<(), E>,
+    {
+        forever_with_config(&self.config, f)
+    }
+}
@@ -0,0 +1,30 @@
+use std::future::Future as StdFuture;
+
+use futures03::compat::Compat;
+
+use run;
+
+
+/// Run the main loop until the `std::future::Future` resolves
+///
+/// This allows to use `async` code with this crate (and with tokio-core
+/// based libraries via `handle()`):
+///
+/// ```ignore
+/// run_std(async {
+///     let data = fetch().compat().await?;
+///     Ok::<_, io::Error>(data)
+/// })
+/// ```
+///
+/// The future is bridged to futures 0.1 via `futures::compat`. This
+/// requires the future to be boxed and pinned, so there is an allocation
+/// per call, and every wakeup goes through the compatibility layer. This
+/// is negligible for a top-level future, but don't use it in hot paths.
+///
+/// Only available with `compat` feature enabled.
+pub fn run_std<F, T, E>(f: F) -> Result<T, E>
+    where F: StdFuture<Output=Result<T, E>>,
+{
+    run(|| Compat::new(Box::pin(f)))
+}
@@ -0,0 +1,101 @@
+use std::cell::Cell;
+
+use futures::{Future, Async, Poll};
+use futures::task;
+
+use spawn;
+
+
+thread_local! {
+    static BUDGET: Cell<Option<u32>> = const { Cell::new(None) };
+}
+
+struct Cooperative<F> {
+    future: F,
+    budget: u32,
+    remaining: u32,
+}
+
+/// Restores the budget of the outer task when the inner poll is finished
+/// (or panics)
+struct Restore(Option<u32>);
+
+/// Spawn a future to the current main loop with a budget of polls
+///
+/// Each poll of the future spends a unit of the budget. When it's
+/// exhausted the future is not polled, instead it yields to other tasks
+/// (like `yield_now()` does) and continues at the next loop iteration with
+/// a fresh budget. This prevents a future which wakes itself up all the
+/// time (e.g. a stream of ready items or a long chain of ready futures)
+/// from starving other tasks of the loop.
+///
+/// It's not possible to preempt a future in the middle of `poll()`. So
+/// CPU-bound code which does a lot of work in a single poll should call
+/// `consume_budget()` which spends the same budget:
+///
+/// ```ignore
+/// spawn_cooperative(stream::iter_ok(0..1000000).for_each(|i| {
+///     process(i);
+///     future::poll_fn(|| Ok(consume_budget()))
+/// }), 1000);
+/// ```
+///
+/// Zero budget is treated as one, i.e. the future yields after every poll.
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn spawn_cooperative<F>(f: F, poll_budget: u32)
+    where F: Future<Item=(), Error=()> + 'static
+{
+    let budget = poll_budget.max(1);
+    spawn(Cooperative { future: f, budget, remaining: budget });
+}
+
+/// Spend a unit of the budget of the task spawned by `spawn_cooperative()`
+///
+/// Returns `NotReady` (and schedules a wakeup of the current task) when
+/// budget is exhausted. Always returns `Ready` outside of tasks spawned
+/// with a budget.
+pub fn consume_budget() -> Async<()> {
+    BUDGET.with(|b| match b.get() {
+        Some(0) => {
+            task::current().notify();
+            Async::NotReady
+        }
+        Some(n) => {
+            b.set(Some(n - 1));
+            Async::Ready(())
+        }
+        None => Async::Ready(()),
+    })
+}
+
+impl<F: Future> Future for Cooperative<F> {
+    type Item = F::Item;
+    type Error = F::Error;
+    fn poll(&mut self) -> Poll<F::Item, F::Error> {
+        if self.remaining == 0 {
+            self.remaining = self.budget;
+            task::current().notify();
+            return Ok(Async::NotReady);
+        }
+        let _restore = Restore(BUDGET.with(|b| {
+            b.replace(Some(self.remaining - 1))
+        }));
+        let result = self.future.poll();
+        self.remaining = match (BUDGET.with(|b| b.get()), &result) {
+            // `consume_budget()` has already yielded
+            (Some(0), &Ok(Async::NotReady)) => self.budget,
+            (remaining, _) => remaining.unwrap_or(0),
+        };
+        result
+    }
+}
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        BUDGET.with(|b| b.set(self.0));
+    }
+}
@@ -0,0 +1,94 @@
+use std::time::Duration;
+
+use futures::{self, IntoFuture};
+
+use bounded::spawn_limit;
+use driver::{drive, new_core, Config};
+use slack::timer_slack;
+use {set_spawn_limit, set_timer_slack};
+
+
+/// Settings applied for the time of the loop run by `run_configured()`
+///
+/// This is an alternative to calling `set_spawn_limit()` and
+/// `set_timer_slack()` before the loop: all the settings are applied when
+/// the loop starts and previous values are restored when it exits.
+#[derive(Debug, Clone, Default)]
+pub struct CoreConfig {
+    name: Option<&'static str>,
+    spawn_limit: Option<usize>,
+    timer_slack: Option<Duration>,
+}
+
+/// Restores thread-local settings when the loop exits (or panics)
+struct Restore {
+    spawn_limit: Option<usize>,
+    timer_slack: Option<Duration>,
+}
+
+impl CoreConfig {
+    /// Create a config with default settings
+    pub fn new() -> CoreConfig {
+        CoreConfig::default()
+    }
+    /// Set the name of the loop, see `run_named()`
+    pub fn name(mut self, name: &'static str) -> CoreConfig {
+        self.name = Some(name);
+        self
+    }
+    /// Set the limit for `spawn_bounded()`, see `set_spawn_limit()`
+    pub fn spawn_limit(mut self, limit: usize) -> CoreConfig {
+        self.spawn_limit = Some(limit);
+        self
+    }
+    /// Set the timer slack, see `set_timer_slack()`
+    pub fn timer_slack(mut self, dur: Duration) -> CoreConfig {
+        self.timer_slack = Some(dur);
+        self
+    }
+}
+
+/// Run the main loop like `run()` with the specified settings
+///
+/// ```ignore
+/// run_configured(CoreConfig::new()
+///     .name("worker")
+///     .spawn_limit(1000)
+///     .timer_slack(Duration::from_millis(50)),
+///     || serve())
+/// ```
+///
+/// Settings which are not set in the config are left intact (i.e. the
+/// ones set by `set_spawn_limit()` and `set_timer_slack()` are used).
+pub fn run_configured<F, R>(config: CoreConfig, f: F)
+    -> Result<R::Item, R::Error>
+    where F: FnOnce() -> R,
+          R: IntoFuture,
+{
+    let _restore = Restore {
+        spawn_limit: config.spawn_limit.map(|limit| {
+            let old = spawn_limit();
+            set_spawn_limit(limit);
+            old
+        }),
+        timer_slack: config.timer_slack.map(|dur| {
+            let old = timer_slack();
+            set_timer_slack(dur);
+            old
+        }),
+    };
+    let mut lp = new_core();
+    drive(&mut lp, &Config { name: config.name, ..Config::default() },
+          futures::lazy(f))
+}
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        if let Some(limit) = self.spawn_limit {
+            set_spawn_limit(limit);
+        }
+        if let Some(dur) = self.timer_slack {
+            set_timer_slack(dur);
+        }
+    }
+}
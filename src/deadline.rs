@@ -0,0 +1,185 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::time::{Duration, Instant};
+
+use futures::{Future, Async, Poll};
+use tokio_core::reactor::Timeout;
+
+use driver::clock_now;
+use timeout_at;
+
+
+/// A future returned by `deadline()`
+///
+/// Resolves to the item of the underlying future or fails with
+/// `DeadlineError::Elapsed` if it's not finished until the deadline.
+pub struct Deadline<F> {
+    future: F,
+    timeout: Timeout,
+}
+
+/// Error returned by `Deadline` future
+#[derive(Debug, PartialEq, Eq)]
+pub enum DeadlineError<E> {
+    /// Deadline elapsed before the underlying future was finished
+    Elapsed,
+    /// The underlying future returned an error
+    Inner(E),
+}
+
+/// A future returned by `timeout_result()`
+pub struct TimeoutResult<F> {
+    deadline: Deadline<F>,
+}
+
+/// Error which is converted into the future's error by `timeout_result()`
+///
+/// It converts to `io::Error` of kind `TimedOut`, implement `From<TimedOut>`
+/// for your own error types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimedOut;
+
+/// Run a future until the specified instant
+///
+/// Returns a future that resolves to the item of `f`, or fails with
+/// `DeadlineError::Elapsed` if `f` is not finished by `at`. If both the
+/// future and the timer are ready at the same time, the future's result
+/// is returned.
+///
+/// # Panics
+///
+/// When no loop is running (`handle()` panics). Also the future panics if
+/// timer fails (which never happens in current tokio)
+pub fn deadline<F: Future>(f: F, at: Instant) -> Deadline<F> {
+    Deadline {
+        future: f,
+        timeout: timeout_at(at),
+    }
+}
+
+/// Run a future for at most the specified duration
+///
+/// This is a shortcut for `deadline(f, Instant::now() + dur)`. For example,
+/// to fail an HTTP request if there is no response in 5 seconds:
+///
+/// ```ignore
+/// with_timeout(client.fetch_url("http://example.com"), Duration::new(5, 0))
+/// .map_err(|e| match e {
+///     DeadlineError::Elapsed => println!("No response in 5 seconds"),
+///     DeadlineError::Inner(e) => println!("Request error: {}", e),
+/// })
+/// ```
+///
+/// Neither the future nor its result is required to be `Send`.
+///
+/// # Panics
+///
+/// When no loop is running (`handle()` panics). Also the future panics if
+/// timer fails (which never happens in current tokio)
+pub fn with_timeout<F: Future>(f: F, dur: Duration) -> Deadline<F> {
+    deadline(f, clock_now() + dur)
+}
+
+/// Run a future for at most the specified duration, reporting timeout as
+/// the future's own error
+///
+/// Unlike `with_timeout()` which wraps errors into `DeadlineError`, this
+/// one converts `TimedOut` into the inner error type, so error type stays
+/// the same as the original future's. Use this one when you don't need to
+/// distinguish timeout from other errors programmatically (e.g. timeout
+/// is just another `io::Error`):
+///
+/// ```
+/// # extern crate futures;
+/// # extern crate tk_easyloop;
+/// # use std::io;
+/// # use std::time::Duration;
+/// # use futures::Future;
+/// # use tk_easyloop::{run, timeout_result};
+/// # fn main() {
+/// let err = run(|| {
+///     timeout_result(futures::empty::<(), io::Error>(),
+///                    Duration::from_millis(10))
+/// }).unwrap_err();
+/// assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+///
+/// // inner error is not masked as a timeout
+/// let err = run(|| {
+///     let failing = futures::failed::<(), _>(
+///         io::Error::new(io::ErrorKind::Other, "inner"));
+///     timeout_result(failing, Duration::new(10, 0))
+/// }).unwrap_err();
+/// assert_eq!(err.kind(), io::ErrorKind::Other);
+/// # }
+/// ```
+///
+/// Use `with_timeout()` or `deadline()` when you need to handle timeouts
+/// separately.
+///
+/// # Panics
+///
+/// When no loop is running (`handle()` panics). Also the future panics if
+/// timer fails (which never happens in current tokio)
+pub fn timeout_result<F>(f: F, dur: Duration) -> TimeoutResult<F>
+    where F: Future,
+          F::Error: From<TimedOut>,
+{
+    TimeoutResult { deadline: with_timeout(f, dur) }
+}
+
+impl<F: Future> Future for Deadline<F> {
+    type Item = F::Item;
+    type Error = DeadlineError<F::Error>;
+    fn poll(&mut self) -> Poll<F::Item, DeadlineError<F::Error>> {
+        match self.future.poll() {
+            Ok(Async::Ready(value)) => return Ok(Async::Ready(value)),
+            Ok(Async::NotReady) => {}
+            Err(e) => return Err(DeadlineError::Inner(e)),
+        }
+        match self.timeout.poll() {
+            Ok(Async::Ready(())) => Err(DeadlineError::Elapsed),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(e) => panic!("timer failed: {}", e),
+        }
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for DeadlineError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DeadlineError::Elapsed => f.write_str("deadline elapsed"),
+            DeadlineError::Inner(ref e) => e.fmt(f),
+        }
+    }
+}
+
+impl<E: Error> Error for DeadlineError<E> {}
+
+impl<F> Future for TimeoutResult<F>
+    where F: Future,
+          F::Error: From<TimedOut>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+    fn poll(&mut self) -> Poll<F::Item, F::Error> {
+        self.deadline.poll().map_err(|e| match e {
+            DeadlineError::Elapsed => TimedOut.into(),
+            DeadlineError::Inner(e) => e,
+        })
+    }
+}
+
+impl fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("timed out")
+    }
+}
+
+impl Error for TimedOut {}
+
+impl From<TimedOut> for io::Error {
+    fn from(e: TimedOut) -> io::Error {
+        io::Error::new(io::ErrorKind::TimedOut, e)
+    }
+}
@@ -0,0 +1,114 @@
+use std::time::Duration;
+
+use futures::{Future, Stream, Async, Poll};
+use tokio_core::reactor::Timeout;
+
+use driver::clock_now;
+use timeout;
+
+
+/// A stream returned by `debounce()`
+pub struct Debounce<S: Stream> {
+    stream: S,
+    quiet: Duration,
+    pending: Option<S::Item>,
+    timer: Option<Timeout>,
+    done: bool,
+}
+
+/// Create a stream which yields an item of `s` only after the source was
+/// quiet for the specified time
+///
+/// Every incoming item resets the timer and replaces the previous item, so
+/// only the most recent item of each burst is yielded. This is useful for
+/// reacting on file changes, where a single save produces multiple events:
+///
+/// ```ignore
+/// spawn_stream(debounce(fs_events, Duration::from_millis(300)), |_| {
+///     reload_config();
+///     Ok(())
+/// })
+/// ```
+///
+/// When the source stream ends, the pending item (if any) is yielded
+/// immediately, and then the stream ends:
+///
+/// ```
+/// # extern crate futures;
+/// # extern crate tk_easyloop;
+/// # use std::time::Duration;
+/// # use futures::{Future, Stream};
+/// # use futures::sync::mpsc;
+/// # use tk_easyloop::{run, spawn, timeout, debounce};
+/// # fn main() {
+/// let items = run(|| {
+///     let (tx, rx) = mpsc::unbounded();
+///     // a burst, `2` is yielded when the source is quiet for 50 ms
+///     tx.unbounded_send(1).unwrap();
+///     tx.unbounded_send(2).unwrap();
+///     spawn(timeout(Duration::from_millis(100)).and_then(move |()| {
+///         tx.unbounded_send(3).unwrap();
+///         timeout(Duration::from_millis(10)).map(move |()| {
+///             // resets the timer, and is yielded when `tx` is dropped
+///             tx.unbounded_send(4).unwrap();
+///         })
+///     }).map_err(|_| ()));
+///     debounce(rx, Duration::from_millis(50)).collect()
+/// }).unwrap();
+/// assert_eq!(items, [2, 4]);
+/// # }
+/// ```
+///
+/// # Panics
+///
+/// The stream panics if it's polled when no loop is running. Also the
+/// stream panics if timer fails (which never happens in current tokio)
+pub fn debounce<S: Stream>(s: S, quiet: Duration) -> Debounce<S> {
+    Debounce {
+        stream: s,
+        quiet,
+        pending: None,
+        timer: None,
+        done: false,
+    }
+}
+
+impl<S: Stream> Stream for Debounce<S> {
+    type Item = S::Item;
+    type Error = S::Error;
+    fn poll(&mut self) -> Poll<Option<S::Item>, S::Error> {
+        while !self.done {
+            match self.stream.poll()? {
+                Async::Ready(Some(item)) => {
+                    self.pending = Some(item);
+                    match self.timer {
+                        Some(ref mut timer) => {
+                            timer.reset(clock_now() + self.quiet);
+                        }
+                        None => self.timer = Some(timeout(self.quiet)),
+                    }
+                }
+                Async::Ready(None) => {
+                    self.done = true;
+                    self.timer = None;
+                    return Ok(Async::Ready(self.pending.take()));
+                }
+                Async::NotReady => break,
+            }
+        }
+        if self.done {
+            return Ok(Async::Ready(None));
+        }
+        if let Some(ref mut timer) = self.timer {
+            match timer.poll() {
+                Ok(Async::Ready(())) => {}
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Err(e) => panic!("timer failed: {}", e),
+            }
+        } else {
+            return Ok(Async::NotReady);
+        }
+        self.timer = None;
+        Ok(Async::Ready(self.pending.take()))
+    }
+}
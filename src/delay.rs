@@ -0,0 +1,92 @@
+use std::time::{Duration, Instant};
+
+use futures::{Future, Async, Poll};
+use tokio_core::reactor::Timeout;
+
+use driver::clock_now;
+use {slack, timeout, timeout_at};
+
+
+/// A future returned by `delay()` and `delay_until()`
+///
+/// Resolves to `()` when the deadline is reached. Unlike `Timeout` it has
+/// `()` as an error type, and unlike `Sleep` it can be rescheduled, so it
+/// can be stored in a struct and used as an idle timer.
+pub struct Delay {
+    timeout: Timeout,
+}
+
+/// Create a future which resolves after the duration
+///
+/// This is useful for an idle timer which is reset on activity:
+///
+/// ```ignore
+/// struct Connection {
+///     idle: Delay,
+/// }
+/// impl Connection {
+///     fn on_data(&mut self) {
+///         self.idle.reset(Duration::new(30, 0));
+///     }
+/// }
+/// ```
+///
+/// # Panics
+///
+/// When no loop is running (`handle()` panics). Also the future panics if
+/// timer fails (which never happens in current tokio)
+pub fn delay(dur: Duration) -> Delay {
+    Delay { timeout: timeout(dur) }
+}
+
+/// Create a future which resolves at the specified instant
+///
+/// # Panics
+///
+/// When no loop is running (`handle()` panics). Also the future panics if
+/// timer fails (which never happens in current tokio)
+pub fn delay_until(instant: Instant) -> Delay {
+    Delay { timeout: timeout_at(instant) }
+}
+
+/// Reschedule the timeout to fire after `dur` from now
+///
+/// This is a shortcut for:
+///
+/// ```ignore
+/// timeout.reset(Instant::now() + dur)
+/// ```
+///
+/// Resetting is much cheaper than dropping the timeout and creating a new
+/// one, so this is what idle timers of connections should do on every
+/// incoming message (timer slack is applied too, see `set_timer_slack()`).
+/// Also see `Delay` which has `reset()` method and doesn't have an error.
+pub fn reset_timeout(timeout: &mut Timeout, dur: Duration) {
+    let at = clock_now() + dur;
+    timeout.reset(slack::deadline(at).unwrap_or(at));
+}
+
+impl Delay {
+    /// Reschedule the delay to resolve after `dur` from now
+    ///
+    /// This works both before and after the delay is resolved.
+    pub fn reset(&mut self, dur: Duration) {
+        reset_timeout(&mut self.timeout, dur);
+    }
+    /// Reschedule the delay to resolve at the specified instant
+    pub fn reset_at(&mut self, instant: Instant) {
+        self.timeout.reset(slack::deadline(instant).unwrap_or(instant));
+    }
+}
+
+impl Future for Delay {
+    type Item = ();
+    type Error = ();
+    fn poll(&mut self) -> Poll<(), ()> {
+        match self.timeout.poll() {
+            Ok(Async::Ready(())) => Ok(Async::Ready(())),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(e) => panic!("timer failed: {}", e),
+        }
+    }
+}
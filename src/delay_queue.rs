@@ -0,0 +1,145 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+use futures::{Future, Stream, Async, Poll};
+use futures::task::{self, Task};
+use tokio_core::reactor::Timeout;
+
+use driver::clock_now;
+use timeout_at;
+
+
+/// A queue that yields values when their deadlines elapse
+///
+/// Values are inserted with `insert()` and the queue (being a `Stream`)
+/// yields each of them once its deadline is reached. Internally there is
+/// only one `Timeout` which is set to the nearest deadline.
+///
+/// The stream never ends, when queue is empty it's just waiting for the
+/// new values to be inserted.
+///
+/// ```
+/// # extern crate futures;
+/// # extern crate tk_easyloop;
+/// # use std::time::Duration;
+/// # use futures::Stream;
+/// # use tk_easyloop::{run, delay_queue};
+/// # fn main() {
+/// let values = run(|| {
+///     let mut queue = delay_queue();
+///     queue.insert("late", Duration::from_millis(60));
+///     // the timer is moved to the earlier deadline
+///     queue.insert("early", Duration::from_millis(20));
+///     let key = queue.insert("removed", Duration::from_millis(10));
+///     assert_eq!(queue.remove(key), Some("removed"));
+///     queue.take(2).collect()
+/// }).unwrap();
+/// assert_eq!(values, ["early", "late"]);
+/// # }
+/// ```
+pub struct DelayQueue<T> {
+    next_id: u64,
+    items: BTreeMap<(Instant, u64), T>,
+    deadlines: HashMap<u64, Instant>,
+    timer: Option<(Instant, Timeout)>,
+    task: Option<Task>,
+}
+
+/// A key returned by `DelayQueue::insert()` which can be used to remove
+/// the value from the queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DelayKey(u64);
+
+/// Create a new empty `DelayQueue`
+///
+/// This is the same as `DelayQueue::new()`, timer is created on the
+/// current loop when the first value is inserted.
+pub fn delay_queue<T>() -> DelayQueue<T> {
+    DelayQueue::new()
+}
+
+impl<T> DelayQueue<T> {
+    /// Create a new empty queue
+    pub fn new() -> DelayQueue<T> {
+        DelayQueue {
+            next_id: 0,
+            items: BTreeMap::new(),
+            deadlines: HashMap::new(),
+            timer: None,
+            task: None,
+        }
+    }
+    /// Insert a value which will be yielded after `dur` from now
+    pub fn insert(&mut self, value: T, dur: Duration) -> DelayKey {
+        self.insert_at(value, clock_now() + dur)
+    }
+    /// Insert a value which will be yielded at the specified instant
+    pub fn insert_at(&mut self, value: T, at: Instant) -> DelayKey {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.items.insert((at, id), value);
+        self.deadlines.insert(id, at);
+        // the timer might need to be moved to an earlier deadline
+        if let Some(task) = self.task.take() {
+            task.notify();
+        }
+        DelayKey(id)
+    }
+    /// Remove the value from the queue
+    ///
+    /// Returns `None` if the value has already been yielded (or removed).
+    pub fn remove(&mut self, key: DelayKey) -> Option<T> {
+        self.deadlines.remove(&key.0)
+            .and_then(|at| self.items.remove(&(at, key.0)))
+    }
+    /// Returns number of values in the queue
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+    /// Returns `true` if there are no values in the queue
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+impl<T> Default for DelayQueue<T> {
+    fn default() -> DelayQueue<T> {
+        DelayQueue::new()
+    }
+}
+
+impl<T> Stream for DelayQueue<T> {
+    type Item = T;
+    type Error = ();
+    fn poll(&mut self) -> Poll<Option<T>, ()> {
+        loop {
+            let (at, id) = match self.items.keys().next() {
+                Some(&key) => key,
+                None => {
+                    self.task = Some(task::current());
+                    return Ok(Async::NotReady);
+                }
+            };
+            if at <= clock_now() {
+                self.deadlines.remove(&id);
+                let value = self.items.remove(&(at, id));
+                return Ok(Async::Ready(value));
+            }
+            match self.timer {
+                Some((ref mut timer_at, ref mut timer)) => {
+                    if *timer_at != at {
+                        timer.reset(at);
+                        *timer_at = at;
+                    }
+                }
+                None => self.timer = Some((at, timeout_at(at))),
+            }
+            self.task = Some(task::current());
+            match self.timer.as_mut().unwrap().1.poll() {
+                Ok(Async::Ready(())) => continue,
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Err(e) => panic!("timer failed: {}", e),
+            }
+        }
+    }
+}
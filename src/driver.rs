@@ -0,0 +1,411 @@
+use std::cell::{Cell, RefCell};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use futures::{Future, Async, Poll};
+use futures::executor::{Notify, with_notify};
+use futures::task::{self, Task};
+use tokio_core::reactor::Core;
+
+use HANDLE;
+#[cfg(feature="testing")] use testing;
+use metrics::{Counters, poll_count};
+use loop_local::Locals;
+
+
+static NEXT_LOOP_ID: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    static NOW: Cell<Option<Instant>> = const { Cell::new(None) };
+    static STEP_STATE: RefCell<Option<LoopState>> = const { RefCell::new(None) };
+}
+
+scoped_thread_local! {
+    static LOOP: LoopState
+}
+
+/// Settings of the loop to run
+#[derive(Clone, Default)]
+pub(crate) struct Config {
+    pub name: Option<&'static str>,
+    pub catch_panics: bool,
+}
+
+type TurnHook = Box<dyn FnMut() -> bool>;
+
+/// State of the currently running loop (in addition to `HANDLE`)
+struct LoopState {
+    id: u64,
+    name: Option<&'static str>,
+    catch_panics: bool,
+    counters: Arc<Counters>,
+    locals: Locals,
+    alive: Arc<AtomicBool>,
+    turn_hooks: RefCell<Vec<TurnHook>>,
+}
+
+/// A counter of loop iterations returned by `turn_counter()`
+///
+/// The counter can be sent to another thread, so it can be used to check
+/// whether the loop is still alive (i.e. the value is still growing).
+#[derive(Clone, Debug)]
+pub struct TurnCounter(Arc<Counters>);
+
+impl TurnCounter {
+    /// Returns the number of loop iterations done so far
+    pub fn get(&self) -> u64 {
+        self.0.turns.load(Ordering::Relaxed)
+    }
+}
+
+/// Polls the main future once per `Core::run` and reports `None` if the
+/// future isn't ready yet
+struct PollOnce<'a, F: 'a>(&'a mut F, &'a Arc<MainNotify>);
+
+/// Records that the main future was woken up, so only then we poll it
+///
+/// Calling `Core::run` on every iteration would make the loop spin, as
+/// `Core::run` always wakes up the reactor for the first poll.
+struct MainNotify {
+    notified: AtomicBool,
+    task: Mutex<Option<Task>>,
+}
+
+impl Notify for MainNotify {
+    fn notify(&self, _: usize) {
+        self.notified.store(true, Ordering::SeqCst);
+        if let Some(ref task) = *self.task.lock().unwrap() {
+            task.notify();
+        }
+    }
+}
+
+impl<'a, F: Future> Future for PollOnce<'a, F> {
+    type Item = Option<F::Item>;
+    type Error = F::Error;
+    fn poll(&mut self) -> Poll<Option<F::Item>, F::Error> {
+        *self.1.task.lock().unwrap() = Some(task::current());
+        let fut = &mut self.0;
+        match with_notify(self.1, 0, || fut.poll())? {
+            Async::Ready(value) => Ok(Async::Ready(Some(value))),
+            Async::NotReady => Ok(Async::Ready(None)),
+        }
+    }
+}
+
+/// Panics if a loop is already running on this thread
+///
+/// Called by `drive()`, and also before touching any thread-local state of
+/// the outer loop (like `shutdown()` sender).
+pub(crate) fn check_not_nested() {
+    if HANDLE.is_set() {
+        panic!("tk-easyloop: run() called while a loop is already running \
+                on this thread; use spawn() or combinators instead \
+                (block_on() is for synchronous code outside of the loop)");
+    }
+}
+
+/// Runs the future on the core with the thread-local handle set
+///
+/// Compared to plain `Core::run` this does loop iterations by itself, so
+/// we can do some housekeeping between iterations. The main future is
+/// polled only on iterations where it was woken up.
+///
+/// Panics if there is a loop already running in this thread, as nested
+/// loop would block the outer one and shadow its handle.
+pub(crate) fn drive<F: Future>(core: &mut Core, config: &Config, mut f: F)
+    -> Result<F::Item, F::Error>
+{
+    check_not_nested();
+    let state = LoopState::new(config);
+    let notify = Arc::new(MainNotify {
+        notified: AtomicBool::new(true),
+        task: Mutex::new(None),
+    });
+    HANDLE.set(&core.handle(), || LOOP.set(&state, || {
+        let result = loop {
+            if notify.notified.swap(false, Ordering::SeqCst) {
+                match core.run(PollOnce(&mut f, &notify)) {
+                    Ok(Some(value)) => break Ok(value),
+                    Ok(None) => {}
+                    Err(e) => break Err(e),
+                }
+            }
+            state.turn(core, None);
+        };
+        NOW.with(|n| n.set(None));
+        result
+    }))
+}
+
+/// Does a single iteration of the loop on the core, see `step()`
+///
+/// The loop state is kept in a thread-local between calls, so the loop
+/// looks like a single running loop for the code spawned on it.
+pub(crate) fn step_core(core: &mut Core, max: Option<Duration>) -> bool {
+    if HANDLE.is_set() {
+        panic!("tk-easyloop: step() called while a loop is already running \
+                on this thread");
+    }
+    STEP_STATE.with(|cell| {
+        let mut state = cell.borrow_mut();
+        let state = state.get_or_insert_with(
+            || LoopState::new(&Config::default()));
+        let polls = poll_count();
+        HANDLE.set(&core.handle(), || LOOP.set(state, || {
+            state.turn(core, max);
+            NOW.with(|n| n.set(None));
+        }));
+        poll_count() != polls
+    })
+}
+
+/// Drops the loop state kept by `step()`
+pub(crate) fn reset_step_state() {
+    STEP_STATE.with(|cell| cell.borrow_mut().take());
+}
+
+impl LoopState {
+    fn new(config: &Config) -> LoopState {
+        LoopState {
+            id: NEXT_LOOP_ID.fetch_add(1, Ordering::Relaxed),
+            name: config.name,
+            catch_panics: config.catch_panics,
+            counters: Arc::new(Counters::default()),
+            locals: Locals::default(),
+            alive: Arc::new(AtomicBool::new(true)),
+            turn_hooks: RefCell::new(Vec::new()),
+        }
+    }
+    fn turn(&self, core: &mut Core, max: Option<Duration>) {
+        NOW.with(|n| n.set(None));
+        core.turn(max);
+        self.counters.turns.fetch_add(1, Ordering::Relaxed);
+        self.run_turn_hooks();
+    }
+    fn run_turn_hooks(&self) {
+        if self.turn_hooks.borrow().is_empty() {
+            return;
+        }
+        // hooks are taken out, so they can register new hooks
+        let mut hooks = self.turn_hooks.replace(Vec::new());
+        hooks.retain_mut(|hook| hook());
+        let mut current = self.turn_hooks.borrow_mut();
+        hooks.append(&mut current);
+        *current = hooks;
+    }
+}
+
+impl Drop for LoopState {
+    fn drop(&mut self) {
+        // also on panic
+        self.alive.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Returns a flag which is `true` while the current loop is running
+///
+/// Panics if no loop is running.
+pub(crate) fn alive_flag() -> Arc<AtomicBool> {
+    LOOP.with(|state| state.alive.clone())
+}
+
+/// Returns the name of the current loop
+///
+/// The name is set by `run_named()` or `Loop::name()`. Returns `None` if
+/// the loop has no name or no loop is running. This is useful for
+/// including the loop name in log messages:
+///
+/// ```ignore
+/// eprintln!("[{}] connection closed", loop_name().unwrap_or("main"));
+/// ```
+pub fn loop_name() -> Option<&'static str> {
+    if LOOP.is_set() {
+        LOOP.with(|state| state.name)
+    } else {
+        None
+    }
+}
+
+/// Returns unique identifier of the current loop
+///
+/// Identifiers are unique within the process, every `run()` (and other
+/// `run*` functions) gets a new one, even if the `Core` is reused. You
+/// can store the identifier in the object tied to the loop to check later
+/// that it's used on the same loop.
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn loop_id() -> u64 {
+    LOOP.with(|state| state.id)
+}
+
+/// Returns `true` if the loop with the specified `loop_id()` is running
+///
+/// Returns `false` if no loop is running or another loop is running. This
+/// is useful to check in debug builds that an object is used on the loop
+/// it was created on:
+///
+/// ```ignore
+/// debug_assert!(is_running_on(self.loop_id));
+/// ```
+pub fn is_running_on(id: u64) -> bool {
+    LOOP.is_set() && LOOP.with(|state| state.id == id)
+}
+
+/// Returns `true` if futures spawned by `spawn()` should catch panics
+pub(crate) fn catch_panics() -> bool {
+    LOOP.is_set() && LOOP.with(|state| state.catch_panics)
+}
+
+/// Calls `f` with the counters of the current loop, does nothing if no
+/// loop is running
+pub(crate) fn with_counters<F: FnOnce(&Arc<Counters>)>(f: F) {
+    if LOOP.is_set() {
+        LOOP.with(|state| f(&state.counters))
+    }
+}
+
+/// Calls `f` with the loop-local values of the current loop
+///
+/// Panics if no loop is running.
+pub(crate) fn with_locals<F: FnOnce(&Locals) -> R, R>(f: F) -> R {
+    if !LOOP.is_set() {
+        panic!("tk-easyloop: loop-local variable accessed outside of the loop");
+    }
+    LOOP.with(|state| f(&state.locals))
+}
+
+/// Returns the number of iterations the current loop has done so far
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn turn_count() -> u64 {
+    LOOP.with(|state| state.counters.turns.load(Ordering::Relaxed))
+}
+
+/// Returns a counter of the current loop iterations, that can be sent to
+/// other threads
+///
+/// This is useful for watchdog thread, which checks that the loop is not
+/// stalled:
+///
+/// ```ignore
+/// let counter = turn_counter();
+/// thread::spawn(move || {
+///     let mut last = counter.get();
+///     loop {
+///         thread::sleep(Duration::new(10, 0));
+///         let turns = counter.get();
+///         if turns == last {
+///             eprintln!("Loop is stalled");
+///         }
+///         last = turns;
+///     }
+/// });
+/// ```
+///
+/// Note: the counter doesn't grow when the loop is idle, so the watchdog
+/// should be combined with some `interval()` running in the loop.
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn turn_counter() -> TurnCounter {
+    LOOP.with(|state| TurnCounter(state.counters.clone()))
+}
+
+/// Run a function once per loop iteration
+///
+/// The function is called after each iteration of the loop until it
+/// returns `false`. This doesn't allocate a timer, so it's useful for
+/// very cheap periodic work like refilling a token bucket or sampling a
+/// counter:
+///
+/// ```ignore
+/// let tokens = tokens.clone();
+/// on_every_turn(move || {
+///     tokens.set(cmp::min(tokens.get() + 1, MAX_TOKENS));
+///     true
+/// });
+/// ```
+///
+/// Note: the loop doesn't iterate when idle, so the function may not be
+/// called for a long time. And any heavy work here stalls the whole loop,
+/// use `interval()` for anything non-trivial.
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn on_every_turn<F>(f: F)
+    where F: FnMut() -> bool + 'static
+{
+    LOOP.with(|state| state.turn_hooks.borrow_mut().push(Box::new(f)))
+}
+
+/// Returns uncached current time of the clock used by loops
+pub(crate) fn clock_now() -> Instant {
+    #[cfg(feature="testing")]
+    {
+        testing::clock_now()
+    }
+    #[cfg(not(feature="testing"))]
+    {
+        Instant::now()
+    }
+}
+
+/// Drops the time cached by `now()`
+#[cfg(feature="testing")]
+pub(crate) fn clear_now() {
+    NOW.with(|n| n.set(None));
+}
+
+/// Creates a new loop
+///
+/// With the `testing` feature its timer follows the clock which can be
+/// paused by `pause()`.
+pub(crate) fn new_core() -> Core {
+    #[cfg(feature="testing")]
+    {
+        testing::new_core()
+    }
+    #[cfg(not(feature="testing"))]
+    {
+        Core::new().expect("create loop")
+    }
+}
+
+/// Returns current time cached for the current loop iteration
+///
+/// Time is fetched for the first call within a loop iteration and then
+/// cached until the loop blocks for the next events. So the value may lag
+/// by up to one loop iteration. This is fine for scheduling timeouts and
+/// for logging, but not for fine-grained benchmarks.
+///
+/// When called outside of the loop it's just `Instant::now()`. When the
+/// clock is paused by `pause()` (`testing` feature) virtual time is
+/// returned.
+pub fn now() -> Instant {
+    NOW.with(|n| {
+        match n.get() {
+            Some(time) => time,
+            None => {
+                let time = clock_now();
+                // only cache inside of the loop, as nothing clears the
+                // cache for `with_test_handle()`
+                if LOOP.is_set() {
+                    n.set(Some(time));
+                }
+                time
+            }
+        }
+    })
+}
@@ -0,0 +1,79 @@
+use std::fmt::Display;
+use std::time::{Duration, Instant};
+
+use futures::Future;
+use tokio_core::reactor::{Handle, Timeout, Interval};
+
+use driver::clock_now;
+use log_errors::log_error;
+use metrics::{self, Counted};
+use {slack, Sleep};
+
+
+/// Extension trait providing the helpers of this crate on an explicit
+/// `Handle`
+///
+/// The free functions like `timeout()` are shortcuts for calling these
+/// methods on the thread-local handle. This trait is useful for code that
+/// deliberately passes handles around (or gets them from other libraries):
+///
+/// ```ignore
+/// fn start(handle: &Handle) {
+///     handle.spawn_log(handle.sleep(Duration::new(1, 0))
+///         .and_then(|()| connect()));
+/// }
+/// ```
+///
+/// The timer slack, metrics and virtual clock are still taken from the
+/// current thread.
+pub trait EasyHandle {
+    /// Create a timeout on this loop, see `timeout()`
+    fn timeout(&self, dur: Duration) -> Timeout;
+    /// Create a timeout on this loop, see `timeout_at()`
+    fn timeout_at(&self, instant: Instant) -> Timeout;
+    /// Create a future that resolves after the duration, see `sleep()`
+    fn sleep(&self, dur: Duration) -> Sleep;
+    /// Create an interval on this loop, see `interval()`
+    fn interval(&self, dur: Duration) -> Interval;
+    /// Create an interval on this loop, see `interval_at()`
+    fn interval_at(&self, at: Instant, dur: Duration) -> Interval;
+    /// Spawn a future on this loop logging its error, see `spawn_log()`
+    fn spawn_log<F>(&self, f: F)
+        where F: Future<Item=()> + 'static,
+              F::Error: Display;
+}
+
+impl EasyHandle for Handle {
+    fn timeout(&self, dur: Duration) -> Timeout {
+        metrics::timer_created();
+        let at = clock_now() + dur;
+        let at = slack::deadline(at).unwrap_or(at);
+        Timeout::new_at(at, self).unwrap()
+    }
+    fn timeout_at(&self, instant: Instant) -> Timeout {
+        metrics::timer_created();
+        let instant = slack::deadline(instant).unwrap_or(instant);
+        Timeout::new_at(instant, self).unwrap()
+    }
+    fn sleep(&self, dur: Duration) -> Sleep {
+        Sleep::new(self.timeout(dur))
+    }
+    fn interval(&self, dur: Duration) -> Interval {
+        metrics::timer_created();
+        let at = clock_now() + dur;
+        match (slack::deadline(at), slack::period(dur)) {
+            (Some(at), Some(dur)) => Interval::new_at(at, dur, self).unwrap(),
+            _ => Interval::new_at(at, dur, self).unwrap(),
+        }
+    }
+    fn interval_at(&self, at: Instant, dur: Duration) -> Interval {
+        metrics::timer_created();
+        Interval::new_at(at, dur, self).unwrap()
+    }
+    fn spawn_log<F>(&self, f: F)
+        where F: Future<Item=()> + 'static,
+              F::Error: Display,
+    {
+        self.spawn(Counted::new(f.map_err(|e| log_error(&e))));
+    }
+}
@@ -0,0 +1,120 @@
+use std::ops::Deref;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use tokio_core::reactor::{Handle, Remote};
+
+use HANDLE;
+use driver::alive_flag;
+
+
+/// Extension trait to check which loop the handle belongs to
+pub trait HandleExt {
+    /// Returns `true` if this handle refers to the currently running loop
+    ///
+    /// Returns `false` if no loop is running. This allows to catch errors
+    /// like using a timer created on loop A while running loop B:
+    ///
+    /// ```ignore
+    /// debug_assert!(self.handle.is_current());
+    /// ```
+    fn is_current(&self) -> bool;
+}
+
+/// A guard returned by `handle_guard()`, dereferences to `Handle`
+pub struct HandleGuard {
+    handle: Handle,
+}
+
+/// Returns a guard which holds a clone of the current loop handle
+///
+/// This is useful in functions which need the handle multiple times, as
+/// `handle()` clones it on each call:
+///
+/// ```ignore
+/// let h = handle_guard();
+/// let listener = TcpListener::bind(&addr, &h)?;
+/// let timeout = Timeout::new(dur, &h)?;
+/// ```
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn handle_guard() -> HandleGuard {
+    HandleGuard { handle: HANDLE.with(|h| h.clone()) }
+}
+
+/// A reference to a loop that is only usable while the loop is running,
+/// returned by `weak_loop()`
+///
+/// Unlike `Remote`, which can be used to spawn futures that are silently
+/// dropped after the loop exits, this allows to check whether the loop is
+/// still running.
+#[derive(Clone)]
+pub struct WeakLoop {
+    remote: Remote,
+    alive: Arc<AtomicBool>,
+}
+
+/// Returns a weak reference to the current loop
+///
+/// The reference can be sent to other threads:
+///
+/// ```ignore
+/// let weak = weak_loop();
+/// thread::spawn(move || {
+///     while let Some(remote) = weak.upgrade() {
+///         remote.spawn(|_| report_progress());
+///         thread::sleep(Duration::new(1, 0));
+///     }
+/// });
+/// ```
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn weak_loop() -> WeakLoop {
+    WeakLoop {
+        remote: HANDLE.with(|h| h.remote().clone()),
+        alive: alive_flag(),
+    }
+}
+
+impl WeakLoop {
+    /// Returns a remote handle if the loop is still running
+    ///
+    /// The loop may still exit right after this method returns, so futures
+    /// spawned on the returned remote may be dropped without running.
+    pub fn upgrade(&self) -> Option<Remote> {
+        if self.alive.load(Ordering::SeqCst) {
+            Some(self.remote.clone())
+        } else {
+            None
+        }
+    }
+    /// Returns `true` if the loop is still running
+    pub fn is_alive(&self) -> bool {
+        self.alive.load(Ordering::SeqCst)
+    }
+}
+
+impl Deref for HandleGuard {
+    type Target = Handle;
+    fn deref(&self) -> &Handle {
+        &self.handle
+    }
+}
+
+impl HandleExt for Handle {
+    fn is_current(&self) -> bool {
+        HANDLE.is_set() && HANDLE.with(|h| h.id() == self.id())
+    }
+}
+
+impl HandleExt for Remote {
+    fn is_current(&self) -> bool {
+        HANDLE.is_set() && HANDLE.with(|h| h.id() == self.id())
+    }
+}
@@ -0,0 +1,172 @@
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+use futures::{Future, Stream, Async, Poll};
+use tokio_core::reactor::Timeout;
+
+use driver::clock_now;
+use {timeout, DeadlineError};
+
+
+/// A stream returned by `idle_timeout()`
+pub struct IdleTimeout<S> {
+    stream: S,
+    dur: Duration,
+    timer: Timeout,
+}
+
+/// A stream returned by `first_item_timeout()`
+pub struct FirstItemTimeout<S> {
+    stream: S,
+    timer: Option<Timeout>,
+}
+
+/// Error returned by `IdleTimeout` stream
+#[derive(Debug, PartialEq, Eq)]
+pub enum IdleError<E> {
+    /// No items were received for the specified duration
+    Idle,
+    /// The underlying stream returned an error
+    Inner(E),
+}
+
+/// Create a stream which fails if `s` doesn't yield an item for `dur`
+///
+/// Unlike `with_timeout()` which limits the total time, this limits the
+/// time between items (and before the first one). This is useful to close
+/// connections which are idle for too long:
+///
+/// ```
+/// # extern crate futures;
+/// # extern crate tk_easyloop;
+/// # use std::time::Duration;
+/// # use futures::Stream;
+/// # use tk_easyloop::{run, interval, idle_timeout, IdleError};
+/// # fn main() {
+/// let ticks = || interval(Duration::from_millis(100)).take(3);
+/// // items arrive in time
+/// run(|| idle_timeout(ticks(), Duration::from_millis(200)).for_each(|()| {
+///     Ok(())
+/// })).unwrap();
+/// // gap between items is too large
+/// let result = run(|| {
+///     idle_timeout(ticks(), Duration::from_millis(50)).for_each(|()| Ok(()))
+/// });
+/// match result {
+///     Err(IdleError::Idle) => {}
+///     _ => panic!("timeout expected"),
+/// }
+/// # }
+/// ```
+///
+/// # Panics
+///
+/// When no loop is running (`handle()` panics). Also the stream panics if
+/// timer fails (which never happens in current tokio)
+pub fn idle_timeout<S: Stream>(s: S, dur: Duration) -> IdleTimeout<S> {
+    IdleTimeout {
+        stream: s,
+        dur,
+        timer: timeout(dur),
+    }
+}
+
+impl<S: Stream> Stream for IdleTimeout<S> {
+    type Item = S::Item;
+    type Error = IdleError<S::Error>;
+    fn poll(&mut self) -> Poll<Option<S::Item>, IdleError<S::Error>> {
+        match self.stream.poll().map_err(IdleError::Inner)? {
+            Async::Ready(item) => {
+                self.timer.reset(clock_now() + self.dur);
+                return Ok(Async::Ready(item));
+            }
+            Async::NotReady => {}
+        }
+        match self.timer.poll() {
+            Ok(Async::Ready(())) => Err(IdleError::Idle),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(e) => panic!("timer failed: {}", e),
+        }
+    }
+}
+
+/// Create a stream which fails if `s` doesn't yield the first item in
+/// `dur`
+///
+/// After the first item, the rest of the stream is passed through without
+/// a timeout. This is useful for request-response protocols, where the
+/// response should start in a limited time but may take long to transfer.
+/// This is unlike `idle_timeout()` limiting every gap between items and
+/// `with_timeout()` limiting the total time:
+///
+/// ```
+/// # extern crate futures;
+/// # extern crate tk_easyloop;
+/// # use std::time::Duration;
+/// # use futures::{Future, Stream};
+/// # use tk_easyloop::{run, interval, timeout, first_item_timeout};
+/// # use tk_easyloop::DeadlineError;
+/// # fn main() {
+/// // the first item is late
+/// let result = run(|| {
+///     let items = interval(Duration::from_millis(100)).take(2);
+///     first_item_timeout(items, Duration::from_millis(50)).collect()
+/// });
+/// match result {
+///     Err(DeadlineError::Elapsed) => {}
+///     _ => panic!("timeout expected"),
+/// }
+/// // the first item is prompt, the next one is slow
+/// let items = run(|| {
+///     let first = timeout(Duration::from_millis(10)).map(|()| 1);
+///     let second = timeout(Duration::from_millis(100)).map(|()| 2);
+///     let items = first.into_stream().chain(second.into_stream());
+///     first_item_timeout(items, Duration::from_millis(50)).collect()
+/// }).map_err(|_| ()).unwrap();
+/// assert_eq!(items, vec![1, 2]);
+/// # }
+/// ```
+///
+/// # Panics
+///
+/// When no loop is running (`handle()` panics). Also the stream panics if
+/// timer fails (which never happens in current tokio)
+pub fn first_item_timeout<S: Stream>(s: S, dur: Duration)
+    -> FirstItemTimeout<S>
+{
+    FirstItemTimeout {
+        stream: s,
+        timer: Some(timeout(dur)),
+    }
+}
+
+impl<S: Stream> Stream for FirstItemTimeout<S> {
+    type Item = S::Item;
+    type Error = DeadlineError<S::Error>;
+    fn poll(&mut self) -> Poll<Option<S::Item>, DeadlineError<S::Error>> {
+        match self.stream.poll().map_err(DeadlineError::Inner)? {
+            Async::Ready(item) => {
+                self.timer = None;
+                return Ok(Async::Ready(item));
+            }
+            Async::NotReady => {}
+        }
+        match self.timer.as_mut().map(|t| t.poll()) {
+            Some(Ok(Async::Ready(()))) => Err(DeadlineError::Elapsed),
+            Some(Ok(Async::NotReady)) | None => Ok(Async::NotReady),
+            Some(Err(e)) => panic!("timer failed: {}", e),
+        }
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for IdleError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            IdleError::Idle => f.write_str("idle timeout elapsed"),
+            IdleError::Inner(ref e) => e.fmt(f),
+        }
+    }
+}
+
+impl<E: Error> Error for IdleError<E> {}
@@ -0,0 +1,196 @@
+use std::io;
+use std::time::{Duration, Instant};
+
+use futures::{Future, Stream, Async, Poll};
+use tokio_core::reactor::{Interval, Timeout};
+
+use {slack, interval, now, timeout_at};
+
+
+/// A stream returned by `interval_tagged()`
+pub struct TaggedInterval {
+    intervals: Vec<(u32, Interval)>,
+    next: usize,
+}
+
+/// What to do when ticks of `interval_policy()` are missed
+///
+/// Ticks are missed when the task consuming the stream is blocked (or
+/// the loop is blocked) for longer than the interval duration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MissedTick {
+    /// Yield all the missed ticks as quick as possible (this is what the
+    /// plain `interval()` does)
+    Burst,
+    /// Yield a single tick, and schedule the next one after the full
+    /// duration from now
+    Delay,
+    /// Yield a single tick, and skip missed ones, so the next tick is
+    /// aligned to the original schedule
+    Skip,
+}
+
+/// A stream returned by `interval_policy()`
+pub struct PolicyInterval {
+    dur: Duration,
+    policy: MissedTick,
+    next: Instant,
+    timer: Timeout,
+}
+
+/// A stream returned by `interval_until()`
+pub struct IntervalUntil {
+    interval: Interval,
+    end: Timeout,
+}
+
+/// Create a stream which yields every `dur` until the specified instant
+///
+/// This is useful for the periodic work that should run only for a
+/// bounded window, e.g. poll aggressively for 30 seconds after a trigger:
+///
+/// ```ignore
+/// let until = now() + Duration::new(30, 0);
+/// spawn_stream(interval_until(Duration::from_millis(100), until), |()| {
+///     check_status();
+///     Ok(())
+/// })
+/// ```
+///
+/// The stream ends when `until` passes, even if the next tick is not
+/// reached yet.
+///
+/// # Panics
+///
+/// When no loop is running (`handle()` panics)
+pub fn interval_until(dur: Duration, until: Instant) -> IntervalUntil {
+    IntervalUntil {
+        interval: interval(dur),
+        end: timeout_at(until),
+    }
+}
+
+/// Create a stream merging multiple intervals, yielding a tag of the
+/// interval that fired
+///
+/// This is useful for a single task that needs to react to several
+/// cadences without spawning a task per cadence:
+///
+/// ```ignore
+/// const FLUSH: u32 = 0;
+/// const REPORT: u32 = 1;
+/// interval_tagged(&[
+///     (FLUSH, Duration::new(1, 0)),
+///     (REPORT, Duration::new(60, 0)),
+/// ]).for_each(|tag| match tag {
+///     FLUSH => flush(),
+///     REPORT => report(),
+///     _ => unreachable!(),
+/// })
+/// ```
+///
+/// If multiple intervals fire at the same time, all of the tags are
+/// yielded one by one.
+///
+/// # Panics
+///
+/// When no loop is running (`handle()` panics)
+pub fn interval_tagged(periods: &[(u32, Duration)]) -> TaggedInterval {
+    TaggedInterval {
+        intervals: periods.iter()
+            .map(|&(tag, dur)| (tag, interval(dur)))
+            .collect(),
+        next: 0,
+    }
+}
+
+impl Stream for IntervalUntil {
+    type Item = ();
+    type Error = io::Error;
+    fn poll(&mut self) -> Poll<Option<()>, io::Error> {
+        if self.end.poll()?.is_ready() {
+            return Ok(Async::Ready(None));
+        }
+        self.interval.poll()
+    }
+}
+
+impl Stream for TaggedInterval {
+    type Item = u32;
+    type Error = io::Error;
+    fn poll(&mut self) -> Poll<Option<u32>, io::Error> {
+        let num = self.intervals.len();
+        // start from the next interval after the one that fired last time,
+        // so that frequent intervals don't starve others
+        for offset in 0..num {
+            let idx = (self.next + offset) % num;
+            let (tag, ref mut interval) = self.intervals[idx];
+            if let Async::Ready(Some(())) = interval.poll()? {
+                self.next = idx + 1;
+                return Ok(Async::Ready(Some(tag)));
+            }
+        }
+        if num == 0 {
+            Ok(Async::Ready(None))
+        } else {
+            Ok(Async::NotReady)
+        }
+    }
+}
+
+/// Create an interval with the specified behavior on missed ticks
+///
+/// This is like `interval()`, but allows handlers which do real work on
+/// every tick to avoid a burst of ticks after the loop was blocked:
+///
+/// ```ignore
+/// interval_policy(Duration::new(1, 0), MissedTick::Skip)
+/// .for_each(|()| {
+///     send_heartbeat();
+///     Ok(())
+/// })
+/// ```
+///
+/// # Panics
+///
+/// When no loop is running (`handle()` panics)
+pub fn interval_policy(dur: Duration, policy: MissedTick) -> PolicyInterval {
+    let next = now() + dur;
+    PolicyInterval {
+        dur,
+        policy,
+        next,
+        timer: timeout_at(next),
+    }
+}
+
+impl Stream for PolicyInterval {
+    type Item = ();
+    type Error = io::Error;
+    fn poll(&mut self) -> Poll<Option<()>, io::Error> {
+        match self.timer.poll()? {
+            Async::Ready(()) => {}
+            Async::NotReady => return Ok(Async::NotReady),
+        }
+        let now = now();
+        match self.policy {
+            MissedTick::Burst => self.next += self.dur,
+            MissedTick::Delay => self.next = now + self.dur,
+            MissedTick::Skip => {
+                self.next += self.dur;
+                if self.next <= now && self.dur > Duration::new(0, 0) {
+                    // skipped whole periods don't exceed `now - next + dur`,
+                    // so the sum always fits into `Duration`
+                    let dur = self.dur.as_nanos();
+                    let skip = (now - self.next).as_nanos() / dur * dur + dur;
+                    self.next += Duration::new(
+                        (skip / 1_000_000_000) as u64,
+                        (skip % 1_000_000_000) as u32);
+                }
+            }
+        }
+        // same rounding as for the first deadline in `timeout_at()`
+        self.timer.reset(slack::deadline(self.next).unwrap_or(self.next));
+        Ok(Async::Ready(Some(())))
+    }
+}
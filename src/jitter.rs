@@ -0,0 +1,56 @@
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::time::Duration;
+
+use futures::Future;
+use futures::future::{loop_fn, Loop};
+
+use {spawn, sleep};
+
+
+/// Returns a random duration in the range `base ± jitter`
+///
+/// This is not a good random number generator, but it's good enough to
+/// spread timers across instances and doesn't need `rand` dependency.
+fn jittered(state: &RandomState, seq: u64, base: Duration, jitter: Duration)
+    -> Duration
+{
+    let range = jitter.as_nanos().min(u64::MAX as u128 / 2) as u64;
+    if range == 0 {
+        return base;
+    }
+    let offset = state.hash_one(seq) % (range * 2 + 1);
+    (base + Duration::from_nanos(offset))
+        .saturating_sub(Duration::from_nanos(range))
+}
+
+/// Call a function every `base ± jitter` on the current loop
+///
+/// This is like `repeat()`, but each delay is randomized, so periodic
+/// tasks of many instances started at the same time don't stay aligned
+/// (i.e. don't cause a thundering herd):
+///
+/// ```ignore
+/// repeat_jitter(Duration::new(60, 0), Duration::new(5, 0), || {
+///     refresh_config();
+///     Ok(())
+/// });
+/// ```
+///
+/// Return `Err(())` from the function to stop calling it.
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn repeat_jitter<F>(base: Duration, jitter: Duration, f: F)
+    where F: FnMut() -> Result<(), ()> + 'static
+{
+    let state = RandomState::new();
+    spawn(loop_fn((f, 0), move |(mut f, seq)| {
+        sleep(jittered(&state, seq, base, jitter)).and_then(move |()| {
+            f()?;
+            Ok(Loop::Continue((f, seq + 1)))
+        })
+    }));
+}
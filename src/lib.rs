@@ -4,8 +4,8 @@
 //! * Avoids padding a `Handle` in to every function
 //!
 //! * Mostly avoids common error: `thread 'foo' panicked at 'no Task is
-//! currently running'`, by providing convenient `run` function for all your
-//! code involving futures
+//!   currently running'`, by providing convenient `run` function for all your
+//!   code involving futures
 //!
 //!
 //! # Example
@@ -56,18 +56,157 @@
 
 extern crate futures;
 extern crate tokio_core;
+extern crate tokio_io;
 #[macro_use] extern crate scoped_tls;
+#[cfg(feature="signal")] extern crate ctrlc;
+#[cfg(feature="compat")] extern crate futures03;
+#[cfg(feature="testing")] extern crate tokio_timer;
 
+use std::cell::RefCell;
+use std::error::Error;
+use std::fmt;
+use std::mem;
+use std::panic::AssertUnwindSafe;
 use std::time::{Duration, Instant};
 
-use futures::{IntoFuture, Future, empty};
-use tokio_core::reactor::{Core, Handle, Timeout, Interval};
+use futures::{IntoFuture, Future, Stream, Sink};
+
+pub use futures::sync::{oneshot, mpsc};
+use tokio_core::reactor::{Core, Handle, Remote, Timeout, Interval};
+
+mod abortable;
+mod ambient_deadline;
+mod blocking;
+mod bounded;
+mod builder;
+#[cfg(feature="compat")] mod compat;
+mod cooperative;
+mod core_config;
+mod deadline;
+mod debounce;
+mod delay;
+mod delay_queue;
+mod driver;
+mod easy_handle;
+mod handle_ext;
+mod idle;
+mod intervals;
+mod jitter;
+mod log_errors;
+#[macro_use] mod loop_local;
+mod loop_thread;
+mod measure;
+mod metrics;
+mod net;
+mod panic;
+mod periodic;
+mod pool;
+mod pump;
+mod race;
+mod rate_limit;
+mod retry;
+mod scoped;
+mod server;
+mod sleep;
+mod slack;
+mod tasks;
+mod throttle;
+mod timeout_io;
+mod tracked;
+mod wait_for;
+mod wheel;
+mod yield_now;
+#[cfg(feature="signal")] mod signal;
+#[cfg(feature="testing")] mod testing;
+
+pub use abortable::{AbortHandle, spawn_abortable, TaskScope, scope};
+pub use ambient_deadline::{with_deadline, current_deadline, deadline_remaining};
+pub use ambient_deadline::clamp_to_deadline;
+pub use blocking::spawn_blocking;
+pub use bounded::{set_spawn_limit, spawn_bounded};
+pub use builder::{Loop, Runner};
+#[cfg(feature="compat")] pub use compat::run_std;
+pub use cooperative::{spawn_cooperative, consume_budget};
+pub use core_config::{CoreConfig, run_configured};
+pub use deadline::{Deadline, DeadlineError, deadline, with_timeout};
+pub use deadline::{TimeoutResult, TimedOut, timeout_result};
+pub use debounce::{Debounce, debounce};
+pub use delay::{Delay, delay, delay_until, reset_timeout};
+pub use delay_queue::{DelayQueue, DelayKey, delay_queue};
+pub use driver::{now, loop_id, loop_name, is_running_on};
+pub use driver::{turn_count, turn_counter, TurnCounter, on_every_turn};
+pub use easy_handle::EasyHandle;
+pub use handle_ext::{HandleExt, HandleGuard, handle_guard};
+pub use handle_ext::{WeakLoop, weak_loop};
+pub use idle::{IdleTimeout, IdleError, idle_timeout};
+pub use idle::{FirstItemTimeout, first_item_timeout};
+pub use intervals::{TaggedInterval, interval_tagged};
+pub use intervals::{PolicyInterval, MissedTick, interval_policy};
+pub use intervals::{IntervalUntil, interval_until};
+pub use jitter::repeat_jitter;
+pub use log_errors::{set_error_logger, spawn_log, spawn_timeout};
+pub use log_errors::{set_init_error_hook, run_logged, run_forever_logged};
+pub use loop_local::LoopLocal;
+pub use loop_thread::{LoopThread, spawn_loop, spawn_across};
+pub use measure::{Measure, measure};
+pub use metrics::{LoopMetrics, MetricsHandle, metrics, metrics_handle};
+pub use metrics::{RunStats, run_instrumented};
+pub use net::{tcp_listen, tcp_connect, tcp_from_std};
+pub use net::{udp_bind, udp_from_std};
+pub use net::{ConnectTimeout, ConnectError, tcp_connect_timeout};
+pub use panic::{set_panic_hook, spawn_catch, spawn_or_panic, run_supervised};
+pub use panic::panicked_task;
+pub use panic::{RunError, run_caught, run_caught_timeout};
+pub use periodic::{IntervalHandle, on_interval};
+pub use pool::{ConnectionPool, PoolConfig, PoolGet, PooledConnection, pool};
+pub use pump::{PumpError, pump, spawn_to_channel};
+pub use race::{Race, race};
+pub use rate_limit::{RateLimited, set_spawn_rate, spawn_rate_limited};
+pub use retry::{Backoff, Retry, retry, retry_if};
+pub use scoped::{Scope, run_scoped};
+pub use server::{ServerError, run_server, run_server_future};
+pub use sleep::Sleep;
+pub use slack::set_timer_slack;
+pub use tasks::{spawn_named, spawn_detached, running_tasks, task_table};
+pub use tasks::TaskInfo;
+pub use throttle::{Throttle, throttle};
+pub use timeout_io::{TimeoutIo, timeout_io};
+pub use tracked::{spawn_tracked, tracked_tasks, run_drained, run_joined};
+pub use wait_for::{WaitFor, wait_for};
+pub use wheel::{TimerWheel, WheelTimeout};
+pub use yield_now::{YieldNow, yield_now};
+#[cfg(feature="signal")] pub use signal::{CtrlC, ctrl_c, run_until_ctrlc};
+#[cfg(feature="signal")] pub use signal::abort_on_ctrlc;
+#[cfg(feature="testing")] pub use testing::{pause, advance, resume};
+
+use driver::{drive, catch_panics, check_not_nested, new_core, Config};
+use driver::{step_core, reset_step_state, clock_now};
+use metrics::Counted;
 
 
 scoped_thread_local! {
     static HANDLE: Handle
 }
 
+thread_local! {
+    static SHUTDOWN: RefCell<Option<oneshot::Sender<()>>> =
+        const { RefCell::new(None) };
+    static CACHED_CORE: CachedCore = const { CachedCore(RefCell::new(None)) };
+}
+
+// Core can't be dropped in thread-local destructor, because runtime uses
+// thread-local storage itself when shutting down. So we leak the core if
+// `drop_cached_core()` was not called before thread exit.
+struct CachedCore(RefCell<Option<Core>>);
+
+impl Drop for CachedCore {
+    fn drop(&mut self) {
+        if let Some(core) = self.0.borrow_mut().take() {
+            mem::forget(core);
+        }
+    }
+}
+
 /// Returns current loop handle
 ///
 /// This only works if running inside the `run()` function of the main loop
@@ -80,6 +219,117 @@ pub fn handle() -> Handle {
     HANDLE.with(|handle| handle.clone())
 }
 
+/// Returns current loop handle if there is a loop running
+///
+/// Unlike `handle()` this function never panics, it returns `None` when
+/// called outside of `run()` instead. Useful for library code that wants
+/// to use the loop only if it's available:
+///
+/// ```ignore
+/// if let Some(handle) = try_handle() {
+///     handle.spawn(flush_logs());
+/// }
+/// ```
+pub fn try_handle() -> Option<Handle> {
+    if HANDLE.is_set() {
+        Some(HANDLE.with(|handle| handle.clone()))
+    } else {
+        None
+    }
+}
+
+/// Runs a function with a reference to the current loop handle
+///
+/// This is similar to `handle()` but doesn't clone the handle, which is
+/// useful for creating loop-tied objects in hot paths:
+///
+/// ```ignore
+/// let sock = with_handle(|h| TcpStream::connect(&addr, h));
+/// ```
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn with_handle<F, R>(f: F) -> R
+    where F: FnOnce(&Handle) -> R
+{
+    HANDLE.with(f)
+}
+
+/// Runs a function with the thread-local handle set to the specified one
+///
+/// This doesn't run the loop, so it's useful in unit tests that exercise
+/// code calling `handle()` or `spawn()`, and drive the core manually:
+///
+/// ```
+/// # extern crate futures;
+/// # extern crate tokio_core;
+/// # extern crate tk_easyloop;
+/// # use std::time::Duration;
+/// # use futures::Future;
+/// # use futures::sync::oneshot;
+/// # use tokio_core::reactor::Core;
+/// # use tk_easyloop::{with_test_handle, spawn, sleep};
+/// # fn main() {
+/// let mut core = Core::new().unwrap();
+/// let (tx, rx) = oneshot::channel();
+/// with_test_handle(&core.handle(), || {
+///     spawn(sleep(Duration::from_millis(1)).then(|_| tx.send(())));
+/// });
+/// core.run(rx).unwrap();
+/// # }
+/// ```
+///
+/// Only the handle is set, so functions that require the loop itself
+/// (like `loop_id()` or `shutdown()`) don't work. Timers created inside
+/// the function still need the core to be driven to fire.
+///
+/// # Panics
+///
+/// When called from inside of `run()` or any other running loop
+pub fn with_test_handle<F, R>(handle: &Handle, f: F) -> R
+    where F: FnOnce() -> R
+{
+    if HANDLE.is_set() {
+        panic!("tk-easyloop: with_test_handle() called while a loop is \
+                already running on this thread");
+    }
+    HANDLE.set(handle, f)
+}
+
+/// Returns a remote handle to the current loop
+///
+/// Unlike `Handle` the `Remote` is `Send`, so you can capture it before
+/// spawning worker threads and then inject futures into this specific loop
+/// from the other threads:
+///
+/// ```ignore
+/// let remote = remote();
+/// thread::spawn(move || {
+///     remote.spawn(|handle| do_something(handle));
+/// });
+/// ```
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn remote() -> Remote {
+    HANDLE.with(|handle| handle.remote().clone())
+}
+
+/// Returns a remote handle to the current loop if there is a loop running
+///
+/// This is a non-panicking version of `remote()`
+pub fn try_remote() -> Option<Remote> {
+    if HANDLE.is_set() {
+        Some(HANDLE.with(|handle| handle.remote().clone()))
+    } else {
+        None
+    }
+}
+
 /// Returns `true` if there is an event loop currently running
 ///
 /// This basically returns `false` if and only if `handle()` would panic
@@ -87,6 +337,29 @@ pub fn is_running() -> bool {
     HANDLE.is_set()
 }
 
+/// Panics with a descriptive message if there is no loop running
+///
+/// This is useful for library functions which require a loop, so they
+/// fail early at the API boundary instead of somewhere deep inside:
+///
+/// ```ignore
+/// pub fn connect(addr: SocketAddr) -> Connection {
+///     ensure_running();
+///     // ...
+/// }
+/// ```
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn ensure_running() {
+    if !HANDLE.is_set() {
+        panic!("tk-easyloop: no loop is running in this thread; call this \
+                from within tk_easyloop::run() (or other run* function)");
+    }
+}
+
 /// Run the main loop and initialize it by running a function
 ///
 /// This is basically a shortcut for:
@@ -101,25 +374,186 @@ pub fn is_running() -> bool {
 /// a future that should complete to proceed. And `run_forever()` in server
 /// applications which spawns some listeners and never exits.
 ///
+/// Use `run_logged()` to report the error through the hook set by
+/// `set_init_error_hook()`.
+///
 /// But also initializes thread-local loop handle for the time of loop run
+///
+/// # Panics
+///
+/// When called from inside of another running loop (same applies to all
+/// other `run*` functions)
 pub fn run<F: FnOnce() -> R, R: IntoFuture>(f: F)
     -> Result<R::Item, R::Error>
 {
-    let mut lp = Core::new().expect("create loop");
-    HANDLE.set(&lp.handle(), || {
-        lp.run(futures::lazy(f))
+    let mut lp = new_core();
+    run_with_core(&mut lp, f)
+}
+
+/// Run the main loop and initialize it by running a function which
+/// receives the loop handle
+///
+/// This is like `run()` but passes the handle to the function directly.
+/// This is the natural place to capture the handle (or `handle.remote()`)
+/// for later use, e.g. to pass remote to other threads:
+///
+/// ```ignore
+/// run_with_handle(|handle| {
+///     let remote = handle.remote().clone();
+///     thread::spawn(move || worker(remote));
+///     TcpListener::bind(&addr, handle).unwrap().incoming().for_each(..)
+/// })
+/// ```
+pub fn run_with_handle<F, R>(f: F) -> Result<R::Item, R::Error>
+    where F: FnOnce(&Handle) -> R,
+          R: IntoFuture,
+{
+    run(|| HANDLE.with(f))
+}
+
+/// Run the main loop using a pre-built `Core` and initialize it by running
+/// a function
+///
+/// This is like `run()` but leaves ownership of the core to the caller, so
+/// the same `Core` can be reused for multiple runs (e.g. in a test harness)
+/// without creating a new reactor each time:
+///
+/// ```ignore
+/// let mut core = Core::new().unwrap();
+/// for _ in 0..100 {
+///     run_with_core(&mut core, || timeout(Duration::from_millis(1)))
+///         .unwrap();
+/// }
+/// ```
+///
+/// Initializes thread-local loop handle for the time of loop run
+pub fn run_with_core<F, R>(core: &mut Core, f: F)
+    -> Result<R::Item, R::Error>
+    where F: FnOnce() -> R,
+          R: IntoFuture,
+{
+    drive(core, &Config::default(), futures::lazy(f))
+}
+
+/// Run the main loop like `run()` but with the specified name
+///
+/// The loop name is included in the panic messages of the tasks spawned
+/// by `spawn_named()` and `spawn_catch()`, which is helpful to debug
+/// applications with multiple loops. Name doesn't change the name of the
+/// OS thread.
+pub fn run_named<F, R>(name: &'static str, f: F)
+    -> Result<R::Item, R::Error>
+    where F: FnOnce() -> R,
+          R: IntoFuture,
+{
+    run_with_config(&Config { name: Some(name), ..Config::default() }, f)
+}
+
+fn run_with_config<F, R>(config: &Config, f: F)
+    -> Result<R::Item, R::Error>
+    where F: FnOnce() -> R,
+          R: IntoFuture,
+{
+    let mut lp = new_core();
+    drive(&mut lp, config, futures::lazy(f))
+}
+
+/// Run the main loop on a thread-local cached `Core`
+///
+/// This is like `run()` but the `Core` is created lazily on the first call
+/// and then reused for subsequent calls in the same thread. This is useful
+/// when `run` is entered and exited many times in a row, because creating
+/// a reactor is relatively expensive.
+///
+/// Note: futures spawned and not finished by the previous call are kept
+/// in the core and continue running on the next call.
+///
+/// Call `drop_cached_core()` before exiting the thread, otherwise the core
+/// is leaked.
+///
+/// # Panics
+///
+/// When called re-entrantly (i.e. from inside of `run_cached()`)
+pub fn run_cached<F, R>(f: F) -> Result<R::Item, R::Error>
+    where F: FnOnce() -> R,
+          R: IntoFuture,
+{
+    CACHED_CORE.with(|cell| {
+        let mut cached = cell.0.try_borrow_mut()
+            .expect("tk-easyloop: run_cached() called re-entrantly");
+        if cached.is_none() {
+            *cached = Some(new_core());
+        }
+        run_with_core(cached.as_mut().unwrap(), f)
+    })
+}
+
+/// Drop the `Core` cached by `run_cached()` in the current thread
+///
+/// Does nothing if there is no cached core. This also drops the futures
+/// spawned by `step()` and resets its loop state (`loop_id()`, loop-local
+/// variables...).
+///
+/// # Panics
+///
+/// When called from inside of `run_cached()`
+pub fn drop_cached_core() {
+    CACHED_CORE.with(|cell| {
+        cell.0.try_borrow_mut()
+            .expect("tk-easyloop: drop_cached_core() called inside the loop")
+            .take();
+    });
+    reset_step_state();
+}
+
+/// Do a single iteration of the main loop
+///
+/// This is useful for embedding the loop into another event system (e.g.
+/// a GUI or a game loop), which can't be blocked by `run()`:
+///
+/// ```ignore
+/// run_cached(|| {
+///     spawn(fetch_updates());
+///     Ok::<(), ()>(())
+/// })?;
+/// loop {
+///     while step(Some(Duration::new(0, 0))) {}
+///     render_frame();
+/// }
+/// ```
+///
+/// The loop uses the same thread-local `Core` as `run_cached()`, and the
+/// thread-local handle is set for the time of the iteration, so `spawn()`
+/// and timer functions work in the futures polled by `step()`. The loop
+/// waits for the events up to `max` (or forever if `None`).
+///
+/// Returns `true` if any future spawned by this crate (i.e. not by
+/// `handle().spawn()` directly) was polled during the iteration.
+///
+/// # Panics
+///
+/// When called from inside of `run()` or any other running loop
+pub fn step(max: Option<Duration>) -> bool {
+    CACHED_CORE.with(|cell| {
+        let mut cached = cell.0.try_borrow_mut()
+            .expect("tk-easyloop: step() called re-entrantly");
+        if cached.is_none() {
+            *cached = Some(new_core());
+        }
+        step_core(cached.as_mut().unwrap(), max)
     })
 }
 
 /// Run the main loop and initialize it by running a function, which spawns
-/// more futures in the main loop. Then run loop indefinitely.
+/// more futures in the main loop. Then run loop indefinitely (or until
+/// `shutdown()` is called).
 ///
 /// This is basically a shortcut for:
 ///
 /// ```ignore
 /// let mut lp = Core::new().expect("create loop");
 /// lp.run(futures::lazy(f)
-///        .and_then(|_| empty()))
+///        .and_then(|_| shutdown_receiver.then(|_| Ok(()))))
 /// ```
 ///
 /// The difference between `run()` and `run_forever()` is merely a convenience.
@@ -127,14 +561,229 @@ pub fn run<F: FnOnce() -> R, R: IntoFuture>(f: F)
 /// a future that should complete to proceed. And `run_forever()` in server
 /// applications which spawns some listeners and never exits.
 ///
+/// Returns `Err` only if `f` fails, and `Ok(())` only after `shutdown()` is
+/// called. Use `run_server()` if you need to know why the loop exited, or
+/// `run_forever_logged()` to report the initialization error.
+///
 /// But also initializes thread-local loop handle for the time of loop run
 pub fn run_forever<F: FnOnce() -> Result<(), E>, E>(f: F) -> Result<(), E> {
-    let mut lp = Core::new().expect("create loop");
-    HANDLE.set(&lp.handle(), || {
-        lp.run(futures::lazy(f).and_then(|_| empty()))
+    forever_with_config(&Config::default(), f)
+}
+
+/// Run the main loop like `run_forever()` but with the specified name
+///
+/// See `run_named()` for more info on loop names.
+pub fn run_forever_named<F, E>(name: &'static str, f: F) -> Result<(), E>
+    where F: FnOnce() -> Result<(), E>,
+{
+    forever_with_config(&Config { name: Some(name), ..Config::default() }, f)
+}
+
+fn forever_with_config<F, E>(config: &Config, f: F) -> Result<(), E>
+    where F: FnOnce() -> Result<(), E>,
+{
+    // otherwise the nested loop replaces `shutdown()` of the outer one
+    check_not_nested();
+    let (tx, rx) = oneshot::channel();
+    SHUTDOWN.with(|s| *s.borrow_mut() = Some(tx));
+    let result = until_with_config(config, rx, f);
+    SHUTDOWN.with(|s| s.borrow_mut().take());
+    result
+}
+
+/// Stop the loop started by `run_forever()`
+///
+/// The loop exits at the next iteration and `run_forever()` returns
+/// `Ok(())`. Returns `true` if a loop was signalled and `false` if there is
+/// no `run_forever()` loop on this thread (or it was already signalled).
+pub fn shutdown() -> bool {
+    SHUTDOWN.with(|s| {
+        s.borrow_mut().take()
+            .map(|tx| tx.send(()).is_ok())
+            .unwrap_or(false)
     })
 }
 
+/// Run the main loop and initialize it by running a function, which spawns
+/// more futures in the main loop. Then run loop until `shutdown` resolves.
+///
+/// This is basically a shortcut for:
+///
+/// ```ignore
+/// let mut lp = Core::new().expect("create loop");
+/// lp.run(futures::lazy(f)
+///        .and_then(|_| shutdown.then(|_| Ok(()))))
+/// ```
+///
+/// This is like `run_forever()` but allows the server to exit cleanly.
+/// Use a `oneshot::Receiver` or ctrl-c future as a `shutdown` signal. Note
+/// that both success and error of `shutdown` future stop the loop (e.g.
+/// dropping the sender of the oneshot also shuts the loop down).
+///
+/// But also initializes thread-local loop handle for the time of loop run
+pub fn run_until<S, F, E>(shutdown: S, f: F) -> Result<(), E>
+    where S: Future,
+          F: FnOnce() -> Result<(), E>,
+{
+    until_with_config(&Config::default(), shutdown, f)
+}
+
+fn until_with_config<S, F, E>(config: &Config, shutdown: S, f: F)
+    -> Result<(), E>
+    where S: Future,
+          F: FnOnce() -> Result<(), E>,
+{
+    let mut lp = new_core();
+    drive(&mut lp, config, futures::lazy(f)
+        .and_then(|_| shutdown.then(|_| Ok(()))))
+}
+
+/// Run a future to completion on a temporary loop
+///
+/// This is useful for synchronous code that needs to drive a short future
+/// to completion (e.g. reading config at startup). The temporary loop is
+/// available via `handle()` while the future is running.
+///
+/// # Panics
+///
+/// This function must not be called from inside of a running loop, as it
+/// would block the outer loop (or deadlock). So it panics if `is_running()`
+/// is true. Use `spawn()` or combinators in that case.
+pub fn block_on<F: IntoFuture>(f: F) -> Result<F::Item, F::Error> {
+    if HANDLE.is_set() {
+        panic!("tk-easyloop: block_on() called from inside of a running \
+                loop, use spawn() or combinators instead");
+    }
+    run(|| f)
+}
+
+/// Error returned by `with_loop()`
+#[derive(Debug, PartialEq, Eq)]
+pub enum WithLoopError<E> {
+    /// A loop is already running in this thread, so the future can't be
+    /// driven to completion synchronously
+    Nested,
+    /// The future returned an error
+    Inner(E),
+}
+
+impl<E: fmt::Display> fmt::Display for WithLoopError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WithLoopError::Nested => {
+                f.write_str("can't block on a future inside of a running loop")
+            }
+            WithLoopError::Inner(ref e) => e.fmt(f),
+        }
+    }
+}
+
+impl<E: Error> Error for WithLoopError<E> {}
+
+/// Run the main loop like `run()` unless a loop is already running
+///
+/// This is useful for library functions that have a synchronous API but
+/// use futures internally. Unlike `run()` and `block_on()` it doesn't
+/// panic when called from inside of a loop, but returns
+/// `WithLoopError::Nested` without calling `f` (blocking would stall the
+/// outer loop):
+///
+/// ```ignore
+/// pub fn resolve_sync(name: &str) -> Result<Vec<IpAddr>, Error> {
+///     match with_loop(|| resolve(name)) {
+///         Ok(addrs) => Ok(addrs),
+///         Err(WithLoopError::Nested) => Err(Error::UseAsyncApi),
+///         Err(WithLoopError::Inner(e)) => Err(e),
+///     }
+/// }
+/// ```
+pub fn with_loop<F, R>(f: F) -> Result<R::Item, WithLoopError<R::Error>>
+    where F: FnOnce() -> R,
+          R: IntoFuture,
+{
+    if HANDLE.is_set() {
+        return Err(WithLoopError::Nested);
+    }
+    run(f).map_err(WithLoopError::Inner)
+}
+
+/// Run the main loop like `run()` but for at most `dur`
+///
+/// Returns `Ok(None)` if time elapsed before the future is complete. This
+/// is useful in tests, so that a hung future fails the test instead of
+/// blocking forever:
+///
+/// ```
+/// # extern crate futures;
+/// # extern crate tk_easyloop;
+/// # use std::time::Duration;
+/// # use tk_easyloop::run_timeout;
+/// # fn main() {
+/// let result = run_timeout(|| futures::empty::<(), ()>(),
+///                          Duration::from_millis(10));
+/// assert_eq!(result, Ok(None));
+/// # }
+/// ```
+///
+/// Unlike `with_timeout()` the timer includes the time of running the
+/// initialization function itself.
+pub fn run_timeout<F, R>(f: F, dur: Duration)
+    -> Result<Option<R::Item>, R::Error>
+    where F: FnOnce() -> R,
+          R: IntoFuture,
+{
+    let result = run(|| {
+        deadline(futures::lazy(f), clock_now() + dur)
+    });
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(DeadlineError::Elapsed) => Ok(None),
+        Err(DeadlineError::Inner(e)) => Err(e),
+    }
+}
+
+/// Run the main loop until all the futures are complete and return all
+/// the results
+///
+/// Unlike `join_all` one future's error doesn't cancel the others, each
+/// result is collected independently (in the same order as futures).
+/// The iterator is consumed inside the loop, so the futures may be created
+/// lazily by the iterator itself:
+///
+/// ```
+/// # extern crate futures;
+/// # extern crate tk_easyloop;
+/// # use std::io;
+/// # use std::time::Duration;
+/// # use futures::Future;
+/// # use tk_easyloop::{run_all, timeout};
+/// # fn main() {
+/// let results = run_all(vec![30, 10, 20].into_iter().map(|ms| {
+///     timeout(Duration::from_millis(ms)).and_then(move |()| {
+///         if ms == 20 {
+///             Err(io::Error::new(io::ErrorKind::Other, "failed"))
+///         } else {
+///             Ok(ms)
+///         }
+///     })
+/// }));
+/// let results = results.into_iter()
+///     .map(|r| r.map_err(|e| e.to_string()))
+///     .collect::<Vec<_>>();
+/// assert_eq!(results, vec![Ok(30), Ok(10), Err("failed".to_string())]);
+/// # }
+/// ```
+pub fn run_all<I, F, T, E>(iter: I) -> Vec<Result<T, E>>
+    where I: IntoIterator<Item=F>,
+          F: Future<Item=T, Error=E>,
+{
+    let result = run(|| {
+        futures::future::join_all(iter.into_iter()
+            .map(|f| f.then(Ok::<_, ()>)))
+    });
+    result.expect("futures never fail")
+}
+
 /// Create a timeout tied to the current loop
 ///
 /// This is a shortcut for:
@@ -149,10 +798,10 @@ pub fn run_forever<F: FnOnce() -> Result<(), E>, E>(f: F) -> Result<(), E> {
 ///
 /// (Note: while we technically `unwrap()` constructor it never fails in
 /// current tokio)
+///
+/// The deadline is rounded if `set_timer_slack()` is used.
 pub fn timeout(dur: Duration) -> Timeout {
-    HANDLE.with(|handle| {
-        Timeout::new(dur, handle).unwrap()
-    })
+    HANDLE.with(|handle| handle.timeout(dur))
 }
 
 /// Create a timeout tied to the current loop
@@ -169,10 +818,30 @@ pub fn timeout(dur: Duration) -> Timeout {
 ///
 /// (Note: while we technically `unwrap()` constructor it never fails in
 /// current tokio)
+///
+/// The deadline is rounded if `set_timer_slack()` is used.
 pub fn timeout_at(instant: Instant) -> Timeout {
-    HANDLE.with(|handle| {
-        Timeout::new_at(instant, handle).unwrap()
-    })
+    HANDLE.with(|handle| handle.timeout_at(instant))
+}
+
+/// Create a future that resolves after the specified duration
+///
+/// This is similar to `timeout()` but has `()` as an error type, so it
+/// doesn't need `map_err` to be composed with other futures:
+///
+/// ```ignore
+/// spawn(sleep(Duration::new(1, 0)).and_then(|()| {
+///     println!("one second passed");
+///     Ok(())
+/// }));
+/// ```
+///
+/// # Panics
+///
+/// When no loop is running (`handle()` panics). Also the future panics if
+/// timer fails (which never happens in current tokio)
+pub fn sleep(dur: Duration) -> Sleep {
+    HANDLE.with(|handle| handle.sleep(dur))
 }
 
 /// Create an interval tied to the current loop
@@ -189,10 +858,38 @@ pub fn timeout_at(instant: Instant) -> Timeout {
 ///
 /// (Note: while we technically `unwrap()` constructor it never fails in
 /// current tokio)
+///
+/// Both the first tick and the period are rounded if `set_timer_slack()`
+/// is used.
 pub fn interval(dur: Duration) -> Interval {
-    HANDLE.with(|handle| {
-        Interval::new(dur, handle).unwrap()
-    })
+    HANDLE.with(|handle| handle.interval(dur))
+}
+
+/// Create an interval tied to the current loop which starts at `at`
+///
+/// This is a shortcut for:
+///
+/// ```ignore
+/// Interval::new_at(at, dur, &handle()).unwrap()
+/// ```
+///
+/// It's useful to align ticks to some time boundary, for example to fire
+/// at the start of every minute:
+///
+/// ```ignore
+/// let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+/// let till_minute = Duration::new(60 - since_epoch.as_secs() % 60, 0);
+/// interval_at(Instant::now() + till_minute, Duration::new(60, 0))
+/// ```
+///
+/// # Panics
+///
+/// When no loop is running (`handle()` panics)
+///
+/// (Note: while we technically `unwrap()` constructor it never fails in
+/// current tokio)
+pub fn interval_at(at: Instant, dur: Duration) -> Interval {
+    HANDLE.with(|handle| handle.interval_at(at, dur))
 }
 
 /// Spawn a future to the current main loop
@@ -205,6 +902,9 @@ pub fn interval(dur: Duration) -> Interval {
 /// handle().spawn(f)
 /// ```
 ///
+/// If the loop is created with `Loop::catch_panics(true)` the panics in the
+/// spawned future are caught, like in `spawn_catch()`.
+///
 /// # Panics
 ///
 /// This function panics if there is no currently running loop (i.e. this
@@ -212,7 +912,43 @@ pub fn interval(dur: Duration) -> Interval {
 pub fn spawn<F>(f: F)
     where F: Future<Item=(), Error=()> + 'static
 {
-    HANDLE.with(|handle| handle.spawn(f))
+    if catch_panics() {
+        spawn_catch(AssertUnwindSafe(f))
+    } else {
+        HANDLE.with(|handle| handle.spawn(Counted::new(f)))
+    }
+}
+
+/// Error returned by `try_spawn()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpawnError {
+    /// There is no loop running in the current thread
+    NoLoop,
+}
+
+impl fmt::Display for SpawnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SpawnError::NoLoop => f.write_str("no loop is running"),
+        }
+    }
+}
+
+impl Error for SpawnError {}
+
+/// Spawn a future to the current main loop if there is one
+///
+/// This is a non-panicking version of `spawn()`, useful for library code
+/// that wants to decide on its own fallback when no loop is running.
+pub fn try_spawn<F>(f: F) -> Result<(), SpawnError>
+    where F: Future<Item=(), Error=()> + 'static
+{
+    if HANDLE.is_set() {
+        spawn(f);
+        Ok(())
+    } else {
+        Err(SpawnError::NoLoop)
+    }
 }
 
 /// Spawn a closure to the current main loop
@@ -233,5 +969,313 @@ pub fn spawn_fn<F, R>(f: F)
     where F: FnOnce() -> R + 'static,
           R: IntoFuture<Item=(), Error=()> + 'static
 {
-    HANDLE.with(|handle| handle.spawn_fn(f))
+    if catch_panics() {
+        spawn(futures::lazy(f))
+    } else {
+        HANDLE.with(|handle| handle.spawn_fn(f))
+    }
+}
+
+/// Spawn a future which resolves to the future that does the real work
+///
+/// This is a shortcut for:
+///
+/// ```ignore
+/// spawn(f.flatten())
+/// ```
+///
+/// It's useful for initialization chains where the outer future fetches
+/// something before producing the work future:
+///
+/// ```ignore
+/// spawn_flatten(read_config().map(|cfg| watch_backends(cfg)))
+/// ```
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn spawn_flatten<F, G>(f: F)
+    where F: Future<Item=G, Error=()> + 'static,
+          G: Future<Item=(), Error=()> + 'static,
+{
+    spawn(f.flatten())
+}
+
+/// Spawn a closure that is polled as a future
+///
+/// This is a shortcut for:
+///
+/// ```ignore
+/// spawn(futures::future::poll_fn(f))
+/// ```
+///
+/// It's handy for small polling loops where writing a `Future` impl is
+/// overkill, e.g. draining a non-blocking queue:
+///
+/// ```ignore
+/// spawn_poll_fn(move || {
+///     while let Async::Ready(Some(msg)) = queue.poll()? {
+///         process(msg);
+///     }
+///     Ok(Async::NotReady)
+/// })
+/// ```
+///
+/// Like in any future, the closure must arrange to be woken up (i.e.
+/// poll some future or stream) before returning `NotReady`.
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn spawn_poll_fn<F>(f: F)
+    where F: FnMut() -> futures::Poll<(), ()> + 'static
+{
+    spawn(futures::future::poll_fn(f))
+}
+
+/// Spawn a future to the current main loop and return a channel that
+/// receives its result
+///
+/// This allows to observe completion of the spawned future:
+///
+/// ```
+/// # extern crate futures;
+/// # extern crate tk_easyloop;
+/// # use tk_easyloop::{run, spawn_join};
+/// # fn main() {
+/// let result = run(|| {
+///     spawn_join(futures::future::ok::<_, ()>(42))
+/// }).unwrap();
+/// assert_eq!(result, Ok(42));
+/// # }
+/// ```
+///
+/// If the receiver is dropped the future still runs to completion, the
+/// result is discarded in this case.
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn spawn_join<F, T, E>(f: F) -> oneshot::Receiver<Result<T, E>>
+    where F: Future<Item=T, Error=E> + 'static,
+          T: 'static,
+          E: 'static,
+{
+    let (tx, rx) = oneshot::channel();
+    spawn(f.then(move |result| {
+        tx.send(result).ok();
+        Ok(())
+    }));
+    rx
+}
+
+/// Spawn a future to the current main loop and signal the channel when
+/// it's finished
+///
+/// This is like `spawn_join()` but the result is discarded, and the
+/// sender is supplied by the caller, so the receiving side may be created
+/// before the task (and passed to another task):
+///
+/// ```ignore
+/// let (tx, rx) = oneshot();
+/// spawn_notify(warm_up_cache(), tx);
+/// spawn(rx.then(|_| serve()));
+/// ```
+///
+/// The channel is signalled both on success and on error of the future.
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn spawn_notify<F>(f: F, done: oneshot::Sender<()>)
+    where F: Future<Item=(), Error=()> + 'static
+{
+    spawn(f.then(move |_| {
+        done.send(()).ok();
+        Ok(())
+    }));
+}
+
+/// Spawn a closure to the current main loop and return a channel that
+/// receives the result of the future returned by the closure
+///
+/// This is a combination of `spawn_fn()` and `spawn_join()`: the closure
+/// is executed when the future is first polled on the loop, so it can use
+/// `handle()` and other loop functions.
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn spawn_fn_join<F, R, T, E>(f: F) -> oneshot::Receiver<Result<T, E>>
+    where F: FnOnce() -> R + 'static,
+          R: IntoFuture<Item=T, Error=E> + 'static,
+          T: 'static,
+          E: 'static,
+{
+    spawn_join(futures::lazy(f))
+}
+
+/// Spawn a future that runs a closure for every item of the stream
+///
+/// This is a shortcut for:
+///
+/// ```ignore
+/// spawn(s.for_each(f).map_err(|_| ()))
+/// ```
+///
+/// Note: stream error is discarded, use `map_err` on the stream if you
+/// want to log it.
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn spawn_stream<S, F>(s: S, f: F)
+    where S: Stream + 'static,
+          F: FnMut(S::Item) -> Result<(), ()> + 'static,
+{
+    spawn(s.map_err(|_| ()).for_each(f))
+}
+
+/// Call a function every `dur` on the current loop
+///
+/// This is a shortcut for:
+///
+/// ```ignore
+/// spawn_stream(interval(dur), move |()| f())
+/// ```
+///
+/// Return `Err(())` from the function to stop calling it.
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn repeat<F>(dur: Duration, mut f: F)
+    where F: FnMut() -> Result<(), ()> + 'static
+{
+    spawn_stream(interval(dur), move |()| f())
+}
+
+/// Spawn a task which calls a function and reschedules itself after the
+/// delay returned by that function
+///
+/// The future returned by the function resolves to
+/// `Loop::Continue(delay)` to call the function again after `delay`, or
+/// `Loop::Break(())` to stop (`Loop` here is `futures::future::Loop`).
+/// This is useful for adaptive polling, when delay depends on the result:
+///
+/// ```ignore
+/// spawn_loop_fn(|| fetch_jobs().map(|jobs| {
+///     if jobs.is_empty() {
+///         Loop::Continue(Duration::new(5, 0))
+///     } else {
+///         process(jobs);
+///         Loop::Continue(Duration::new(0, 0))
+///     }
+/// }))
+/// ```
+///
+/// The first call is done immediately. The task stops on error too.
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn spawn_loop_fn<F, R>(f: F)
+    where F: FnMut() -> R + 'static,
+          R: IntoFuture<Item=futures::future::Loop<(), Duration>, Error=()>,
+          R::Future: 'static,
+{
+    use futures::future::{Either, Loop, loop_fn};
+
+    spawn(loop_fn(f, |mut f| {
+        f().into_future().and_then(|step| match step {
+            Loop::Break(()) => Either::A(futures::finished(Loop::Break(()))),
+            Loop::Continue(delay) => {
+                Either::B(sleep(delay).map(move |()| Loop::Continue(f)))
+            }
+        })
+    }))
+}
+
+/// Create a oneshot channel
+///
+/// This is just a re-export of `futures::sync::oneshot::channel()` for
+/// convenience. Both halves can be sent to other threads (if `T: Send`).
+pub fn oneshot<T>() -> (oneshot::Sender<T>, oneshot::Receiver<T>) {
+    oneshot::channel()
+}
+
+/// Create a bounded mpsc channel
+///
+/// This is just a re-export of `futures::sync::mpsc::channel()` for
+/// convenience. Use `spawn_forward()` to feed the receiver into a sink
+/// on the current loop.
+pub fn channel<T>(buffer: usize) -> (mpsc::Sender<T>, mpsc::Receiver<T>) {
+    mpsc::channel(buffer)
+}
+
+/// Spawn a future that forwards all items of the stream into the sink
+///
+/// This is basically a shortcut for:
+///
+/// ```ignore
+/// spawn(stream.forward(sink).map(|_| ()).map_err(|_| ()))
+/// ```
+///
+/// Errors of both the stream and the sink are discarded and stop the
+/// forwarding.
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn spawn_forward<S, K>(stream: S, sink: K)
+    where S: Stream + 'static,
+          K: Sink<SinkItem=S::Item> + 'static,
+{
+    spawn(stream.map_err(|_| ())
+        .forward(sink.sink_map_err(|_| ()))
+        .map(|_| ()))
+}
+
+/// Spawn a future to the current main loop which starts at `at`
+///
+/// The future isn't polled until the specified instant. This is basically:
+///
+/// ```ignore
+/// spawn(timeout_at(at).then(|_| f))
+/// ```
+///
+/// (i.e. the future is started even if timer fails, which never happens in
+/// current tokio)
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn spawn_at<F>(at: Instant, f: F)
+    where F: Future<Item=(), Error=()> + 'static
+{
+    spawn(timeout_at(at).then(|_| f))
+}
+
+/// Spawn a future to the current main loop which starts after `dur`
+///
+/// This is a relative counterpart of `spawn_at()`.
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn spawn_after<F>(dur: Duration, f: F)
+    where F: Future<Item=(), Error=()> + 'static
+{
+    spawn(timeout(dur).then(|_| f))
 }
@@ -0,0 +1,144 @@
+use std::cell::RefCell;
+use std::fmt::Display;
+use std::time::Duration;
+
+use futures::{Future, IntoFuture};
+
+use {spawn, with_timeout, run, run_forever, DeadlineError};
+
+
+type ErrorLogger = Box<dyn Fn(&dyn Display)>;
+
+thread_local! {
+    static ERROR_LOGGER: RefCell<Option<ErrorLogger>> = const { RefCell::new(None) };
+    static INIT_ERROR_HOOK: RefCell<Option<ErrorLogger>> = const { RefCell::new(None) };
+}
+
+pub(crate) fn log_error(err: &dyn Display) {
+    ERROR_LOGGER.with(|logger| {
+        match *logger.borrow() {
+            Some(ref logger) => logger(err),
+            None => eprintln!("tk-easyloop: spawned future failed: {}", err),
+        }
+    })
+}
+
+/// Set a function that is called when future spawned by `spawn_log()`
+/// fails
+///
+/// By default, the error is printed to stderr. This can be used to
+/// integrate with the `log` crate:
+///
+/// ```ignore
+/// set_error_logger(Box::new(|e| error!("{}", e)));
+/// ```
+///
+/// The logger is set for the current thread only.
+pub fn set_error_logger(logger: ErrorLogger) {
+    ERROR_LOGGER.with(|l| *l.borrow_mut() = Some(logger));
+}
+
+/// Set a function that is called when `run_logged()` or
+/// `run_forever_logged()` fails
+///
+/// By default, the error is printed to stderr. This is useful to report
+/// the startup error through the logging system of the application:
+///
+/// ```ignore
+/// set_init_error_hook(Box::new(|e| error!("startup failed: {}", e)));
+/// ```
+///
+/// The hook is set for the current thread only. It's not called by plain
+/// `run()` and `run_forever()`, as they don't require the error to
+/// implement `Display`.
+pub fn set_init_error_hook(hook: ErrorLogger) {
+    INIT_ERROR_HOOK.with(|h| *h.borrow_mut() = Some(hook));
+}
+
+/// Run the main loop like `run_forever()`, reporting the initialization
+/// error
+///
+/// When `f` fails, the error is passed to the function set by
+/// `set_init_error_hook()` and then returned unchanged. So startup errors
+/// of servers are not easy to miss, even if the result is just
+/// `unwrap()`'ed at some place far from the logging setup.
+pub fn run_forever_logged<F, E>(f: F) -> Result<(), E>
+    where F: FnOnce() -> Result<(), E>,
+          E: Display,
+{
+    run_forever(f).map_err(report_init_error)
+}
+
+/// Run the main loop like `run()`, reporting the error
+///
+/// When `f` or the future returned by it fails, the error is passed to
+/// the function set by `set_init_error_hook()` and then returned
+/// unchanged.
+pub fn run_logged<F, R>(f: F) -> Result<R::Item, R::Error>
+    where F: FnOnce() -> R,
+          R: IntoFuture,
+          R::Error: Display,
+{
+    run(f).map_err(report_init_error)
+}
+
+fn report_init_error<E: Display>(e: E) -> E {
+    INIT_ERROR_HOOK.with(|hook| {
+        match *hook.borrow() {
+            Some(ref hook) => hook(&e),
+            None => eprintln!("tk-easyloop: startup failed: {}", e),
+        }
+    });
+    e
+}
+
+/// Spawn a future to the current main loop, logging its error
+///
+/// This is a shortcut for the usual:
+///
+/// ```ignore
+/// spawn(f.map_err(|e| error!("{}", e)))
+/// ```
+///
+/// The error is passed to the function set by `set_error_logger()`.
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn spawn_log<F>(f: F)
+    where F: Future<Item=()> + 'static,
+          F::Error: Display,
+{
+    spawn(f.map_err(|e| log_error(&e)));
+}
+
+/// Spawn a future to the current main loop and drop it if it's not
+/// finished in `dur`
+///
+/// Both the value and the error of the future are discarded. This is
+/// useful for fire-and-forget tasks that should not hang forever, e.g.
+/// pushing metrics to an endpoint that may be dead:
+///
+/// ```ignore
+/// spawn_timeout(push_metrics(&stats), Duration::new(10, 0));
+/// ```
+///
+/// When the timeout fires, a message is passed to the function set by
+/// `set_error_logger()`, so dropped tasks are observable.
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn spawn_timeout<F>(f: F, dur: Duration)
+    where F: Future + 'static,
+{
+    spawn(with_timeout(f, dur).then(move |result| {
+        if let Err(DeadlineError::Elapsed) = result {
+            log_error(&format_args!("spawned future timed out after {:?}",
+                                    dur));
+        }
+        Ok(())
+    }));
+}
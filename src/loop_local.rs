@@ -0,0 +1,101 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use driver::with_locals;
+
+
+/// Values of loop-local variables of the current loop keyed by address of
+/// the `LoopLocal`
+pub(crate) type Locals = RefCell<HashMap<usize, Rc<dyn Any>>>;
+
+/// A key for the loop-local storage, declared by `loop_local!`
+///
+/// Each loop (i.e. each `run()` call) gets its own value initialized on
+/// the first access. The value is dropped when the loop exits.
+pub struct LoopLocal<T: 'static> {
+    init: fn() -> T,
+}
+
+/// Declare a loop-local variable
+///
+/// This is similar to `thread_local!`, but each loop (i.e. each `run()`
+/// call, even on the same thread) has a separate value, which is dropped
+/// when the loop exits:
+///
+/// ```
+/// extern crate tk_easyloop;
+/// # use std::cell::Cell;
+/// tk_easyloop::loop_local! {
+///     static REQUESTS: Cell<u32> = Cell::new(0);
+///     static ERRORS: Cell<u32> = Cell::new(0);
+/// }
+/// # fn main() {
+/// tk_easyloop::run(|| {
+///     REQUESTS.with(|r| r.set(r.get() + 1));
+///     assert_eq!(REQUESTS.with(|r| r.get()), 1);
+///     assert_eq!(ERRORS.with(|e| e.get()), 0);
+///     Ok::<(), ()>(())
+/// }).unwrap();
+/// tk_easyloop::run(|| {
+///     assert_eq!(REQUESTS.with(|r| r.get()), 0);
+///     Ok::<(), ()>(())
+/// }).unwrap();
+/// # }
+/// ```
+///
+/// The macro can also be imported with `#[macro_use]`.
+#[macro_export]
+macro_rules! loop_local {
+    () => {};
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr;
+     $($rest:tt)*) => {
+        $(#[$attr])*
+        $vis static $name: $crate::LoopLocal<$t> = $crate::LoopLocal::new({
+            fn init() -> $t { $init }
+            init
+        });
+        $crate::loop_local!($($rest)*);
+    };
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr) => {
+        $crate::loop_local!($(#[$attr])* $vis static $name: $t = $init;);
+    };
+}
+
+impl<T: 'static> LoopLocal<T> {
+    /// Create a key with the function that initializes the value, use
+    /// `loop_local!` instead
+    pub const fn new(init: fn() -> T) -> LoopLocal<T> {
+        LoopLocal { init }
+    }
+    /// Access the value for the current loop
+    ///
+    /// The value is initialized on the first access within the loop.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if there is no currently running loop (i.e.
+    /// this function is not running from the inside of `run()`.
+    pub fn with<F, R>(&'static self, f: F) -> R
+        where F: FnOnce(&T) -> R
+    {
+        let key = self as *const LoopLocal<T> as usize;
+        // the map isn't borrowed while `f` is running, so `f` can access
+        // other loop-local variables
+        let value = with_locals(|locals| {
+            locals.borrow().get(&key).cloned()
+        });
+        let value = match value {
+            Some(value) => value,
+            None => {
+                let value: Rc<dyn Any> = Rc::new((self.init)());
+                with_locals(|locals| {
+                    locals.borrow_mut().insert(key, value.clone());
+                });
+                value
+            }
+        };
+        f(value.downcast_ref::<T>().expect("loop-local type matches"))
+    }
+}
@@ -0,0 +1,139 @@
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+
+use futures::{Future, IntoFuture};
+use futures::sync::oneshot;
+use tokio_core::reactor::Remote;
+
+use {run_until, spawn, weak_loop, WeakLoop, metrics_handle, MetricsHandle};
+
+
+/// A loop running in a background thread, returned by `spawn_loop()`
+///
+/// Dropping the `LoopThread` stops the loop too, but doesn't wait for the
+/// thread to exit.
+pub struct LoopThread {
+    weak: WeakLoop,
+    remote: Remote,
+    metrics: MetricsHandle,
+    shutdown: Option<oneshot::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// Start a loop in a new thread
+///
+/// This is useful for embedding a loop into a larger synchronous
+/// application:
+///
+/// ```ignore
+/// let lp = spawn_loop();
+/// lp.spawn(|| {
+///     spawn_log(fetch_config().map(|cfg| println!("Config {:?}", cfg)));
+///     Ok(())
+/// });
+/// // ... do other work
+/// lp.shutdown();
+/// ```
+pub fn spawn_loop() -> LoopThread {
+    let (tx, rx) = mpsc::channel();
+    let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+    let thread = thread::Builder::new()
+        .name("tk-easyloop".into())
+        .spawn(move || {
+            run_until(shutdown_rx, || {
+                tx.send((weak_loop(), metrics_handle())).ok();
+                Ok::<(), ()>(())
+            }).ok();
+        })
+        .expect("spawn loop thread");
+    let (weak, metrics): (WeakLoop, _) = rx.recv()
+        .expect("loop thread started");
+    LoopThread {
+        remote: weak.upgrade().expect("loop thread is running"),
+        weak,
+        metrics,
+        shutdown: Some(shutdown_tx),
+        thread: Some(thread),
+    }
+}
+
+impl LoopThread {
+    /// Run a function on the loop, the returned future is spawned there
+    ///
+    /// The function runs inside the loop, so the thread-local helpers
+    /// (`spawn()`, `timeout()`, ...) work there.
+    pub fn spawn<F, R>(&self, f: F)
+        where F: FnOnce() -> R + Send + 'static,
+              R: IntoFuture<Item=(), Error=()>,
+              R::Future: 'static,
+    {
+        self.remote.spawn(move |_| f())
+    }
+    /// Returns a remote handle to the loop
+    pub fn remote(&self) -> &Remote {
+        &self.remote
+    }
+    /// Returns a weak reference to the loop, which can be used to check
+    /// whether the loop is still running
+    pub fn weak_loop(&self) -> WeakLoop {
+        self.weak.clone()
+    }
+    /// Returns a handle to the metrics of the loop
+    pub fn metrics(&self) -> &MetricsHandle {
+        &self.metrics
+    }
+    /// Stop the loop and wait for the thread to exit
+    ///
+    /// Futures spawned on the loop which are not finished yet are dropped.
+    pub fn shutdown(mut self) {
+        if let Some(tx) = self.shutdown.take() {
+            tx.send(()).ok();
+        }
+        if let Some(thread) = self.thread.take() {
+            thread.join().expect("loop thread panicked");
+        }
+    }
+}
+
+/// Spawn futures across multiple loop threads
+///
+/// Each future goes to a loop with the least number of active tasks
+/// (as reported by `LoopThread::metrics()`), counting the futures
+/// distributed by this call too. So futures are spread evenly across idle
+/// loops, and busy loops get less new work:
+///
+/// ```ignore
+/// let loops = (0..4).map(|_| spawn_loop()).collect::<Vec<_>>();
+/// spawn_across(&loops, chunks.into_iter().map(process_chunk).collect());
+/// ```
+///
+/// Only the tasks spawned by this crate's helpers are counted as active.
+///
+/// # Panics
+///
+/// If `loops` is empty (and `futures` is not)
+pub fn spawn_across<F>(loops: &[LoopThread], futures: Vec<F>)
+    where F: Future<Item=(), Error=()> + Send + 'static
+{
+    let mut load = loops.iter()
+        .map(|lp| lp.metrics.get().active_tasks)
+        .collect::<Vec<_>>();
+    for f in futures {
+        let (idx, active) = load.iter_mut().enumerate()
+            .min_by_key(|(_, active)| **active)
+            .expect("spawn_across() requires at least one loop");
+        *active += 1;
+        loops[idx].spawn(move || {
+            spawn(f);
+            Ok(())
+        });
+    }
+}
+
+impl Drop for LoopThread {
+    fn drop(&mut self) {
+        // dropping the sender resolves the shutdown future with an error,
+        // which stops the loop too
+        self.shutdown.take();
+    }
+}
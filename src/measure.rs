@@ -0,0 +1,47 @@
+use std::time::{Duration, Instant};
+
+use futures::{Future, Async, Poll};
+
+use now;
+
+
+/// A future returned by `measure()`
+pub struct Measure<F> {
+    future: F,
+    started: Option<Instant>,
+}
+
+/// Wrap a future to measure how long it takes
+///
+/// The time is measured from the first poll of the future till it
+/// resolves, and is returned along with the item:
+///
+/// ```ignore
+/// spawn_log(measure(fetch(url)).map(|(response, time)| {
+///     println!("Fetched in {:?}", time);
+/// }))
+/// ```
+///
+/// Time is taken from `now()`, so it's cheap, but the precision is
+/// limited to a loop iteration. The time of a failed future isn't
+/// reported.
+pub fn measure<F: Future>(f: F) -> Measure<F> {
+    Measure {
+        future: f,
+        started: None,
+    }
+}
+
+impl<F: Future> Future for Measure<F> {
+    type Item = (F::Item, Duration);
+    type Error = F::Error;
+    fn poll(&mut self) -> Poll<(F::Item, Duration), F::Error> {
+        let started = *self.started.get_or_insert_with(now);
+        match self.future.poll()? {
+            Async::Ready(value) => {
+                Ok(Async::Ready((value, now().duration_since(started))))
+            }
+            Async::NotReady => Ok(Async::NotReady),
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use std::cell::Cell;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use futures::{Future, IntoFuture, Poll};
+use futures::future::lazy;
+
+use driver::{drive, new_core, with_counters, Config};
+use turn_counter;
+
+
+thread_local! {
+    // counted per thread rather than per loop, as futures spawned by
+    // `run_cached()` are polled by `step()` on the same core later
+    static POLLS: Cell<u64> = const { Cell::new(0) };
+}
+
+
+/// Counters of the loop activity, shared with `MetricsHandle`
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    pub turns: AtomicU64,
+    pub spawned: AtomicU64,
+    pub active: AtomicU64,
+    pub timers: AtomicU64,
+}
+
+/// A snapshot of the loop activity returned by `metrics()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoopMetrics {
+    /// Number of futures spawned since the loop was started
+    pub spawned_total: u64,
+    /// Number of spawned futures that are not finished yet
+    pub active_tasks: u64,
+    /// Number of loop iterations, same as `turn_count()`
+    pub turns: u64,
+    /// Number of timers created by `timeout()`, `interval()` and similar
+    pub timers_created: u64,
+}
+
+/// A handle returned by `metrics_handle()` which can be sent to another
+/// thread to sample the metrics of the running loop
+#[derive(Clone, Debug)]
+pub struct MetricsHandle(Arc<Counters>);
+
+/// Timings of the loop returned by `run_instrumented()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RunStats {
+    /// Time spent creating the `Core`
+    pub core_create_time: Duration,
+    /// Time the loop was running (not including `core_create_time`)
+    pub total_run_time: Duration,
+    /// Number of loop iterations
+    pub turn_count: u64,
+}
+
+/// Counts the spawned future as active until it's dropped
+pub(crate) struct Counted<F> {
+    future: F,
+    counters: Option<Arc<Counters>>,
+}
+
+impl Counters {
+    fn snapshot(&self) -> LoopMetrics {
+        LoopMetrics {
+            spawned_total: self.spawned.load(Ordering::Relaxed),
+            active_tasks: self.active.load(Ordering::Relaxed),
+            turns: self.turns.load(Ordering::Relaxed),
+            timers_created: self.timers.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl MetricsHandle {
+    /// Returns a snapshot of the loop metrics
+    ///
+    /// When the loop is finished, values stay the same as they were at the
+    /// end of the loop.
+    pub fn get(&self) -> LoopMetrics {
+        self.0.snapshot()
+    }
+}
+
+impl<F> Counted<F> {
+    pub(crate) fn new(future: F) -> Counted<F> {
+        let mut counters = None;
+        with_counters(|c| {
+            c.spawned.fetch_add(1, Ordering::Relaxed);
+            c.active.fetch_add(1, Ordering::Relaxed);
+            counters = Some(c.clone());
+        });
+        Counted { future, counters }
+    }
+}
+
+impl<F: Future> Future for Counted<F> {
+    type Item = F::Item;
+    type Error = F::Error;
+    fn poll(&mut self) -> Poll<F::Item, F::Error> {
+        POLLS.with(|p| p.set(p.get() + 1));
+        self.future.poll()
+    }
+}
+
+impl<F> Drop for Counted<F> {
+    fn drop(&mut self) {
+        if let Some(ref c) = self.counters {
+            c.active.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Returns number of times futures spawned in the current thread were
+/// polled
+pub(crate) fn poll_count() -> u64 {
+    POLLS.with(|p| p.get())
+}
+
+pub(crate) fn timer_created() {
+    with_counters(|c| {
+        c.timers.fetch_add(1, Ordering::Relaxed);
+    });
+}
+
+/// Returns a snapshot of the current loop activity
+///
+/// Only the futures spawned by this crate's helpers (`spawn()` and the
+/// functions built on it) and the timers created by `timeout()`,
+/// `timeout_at()`, `interval()`, `interval_at()` (and the functions
+/// built on them) are counted.
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn metrics() -> LoopMetrics {
+    metrics_handle().get()
+}
+
+/// Returns a handle to the metrics of the current loop, which can be sent
+/// to other threads
+///
+/// This is useful for exposing the loop metrics over an admin endpoint
+/// served by another thread:
+///
+/// ```ignore
+/// let metrics = metrics_handle();
+/// thread::spawn(move || loop {
+///     thread::sleep(Duration::new(10, 0));
+///     println!("{:?}", metrics.get());
+/// });
+/// ```
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn metrics_handle() -> MetricsHandle {
+    let mut handle = None;
+    with_counters(|c| handle = Some(MetricsHandle(c.clone())));
+    handle.expect("tk-easyloop: metrics requested outside of the loop")
+}
+
+/// Run the main loop like `run()` and return the timings of the loop
+///
+/// This is useful to find out whether reusing the `Core` (see
+/// `run_cached()` and `run_with_core()`) is worth it for your workload:
+///
+/// ```ignore
+/// let (result, stats) = run_instrumented(|| fetch_config());
+/// println!("Core created in {:?}, loop run for {:?} ({} turns)",
+///     stats.core_create_time, stats.total_run_time, stats.turn_count);
+/// ```
+pub fn run_instrumented<F, R>(f: F)
+    -> (Result<R::Item, R::Error>, RunStats)
+    where F: FnOnce() -> R,
+          R: IntoFuture,
+{
+    let start = Instant::now();
+    let mut lp = new_core();
+    let created = Instant::now();
+    let mut counter = None;
+    let result = drive(&mut lp, &Config::default(), lazy(|| {
+        counter = Some(turn_counter());
+        f()
+    }));
+    let stats = RunStats {
+        core_create_time: created.duration_since(start),
+        total_run_time: created.elapsed(),
+        turn_count: counter.map(|c| c.get()).unwrap_or(0),
+    };
+    (result, stats)
+}
@@ -0,0 +1,187 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::net::{self, SocketAddr};
+use std::time::Duration;
+
+use futures::{Future, Async, Poll};
+use tokio_core::net::{TcpListener, TcpStream, TcpStreamNew, UdpSocket};
+use tokio_core::reactor::Timeout;
+
+use {HANDLE, timeout};
+
+
+/// A future returned by `tcp_connect_timeout()`
+pub struct ConnectTimeout {
+    connect: Option<TcpStreamNew>,
+    timeout: Timeout,
+}
+
+/// Error returned by `tcp_connect_timeout()`
+#[derive(Debug)]
+pub enum ConnectError {
+    /// Connection was not established in time
+    TimedOut,
+    /// Connection failed
+    Io(io::Error),
+}
+
+
+/// Create a TCP listener bound to the address on the current loop
+///
+/// This is a shortcut for:
+///
+/// ```ignore
+/// TcpListener::bind(&addr, &handle())
+/// ```
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn tcp_listen(addr: SocketAddr) -> io::Result<TcpListener> {
+    HANDLE.with(|handle| TcpListener::bind(&addr, handle))
+}
+
+/// Connect to the TCP address on the current loop
+///
+/// This is a shortcut for:
+///
+/// ```ignore
+/// TcpStream::connect(&addr, &handle())
+/// ```
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn tcp_connect(addr: SocketAddr) -> TcpStreamNew {
+    HANDLE.with(|handle| TcpStream::connect(&addr, handle))
+}
+
+/// Connect to the TCP address on the current loop, giving up after `dur`
+///
+/// OS connect timeouts are long and unpredictable, so this is what you
+/// usually want for connecting to other services:
+///
+/// ```ignore
+/// tcp_connect_timeout(addr, Duration::new(5, 0))
+/// .map_err(|e| match e {
+///     ConnectError::TimedOut => println!("Connect timed out"),
+///     ConnectError::Io(e) => println!("Connect error: {}", e),
+/// })
+/// ```
+///
+/// When the timeout elapses, in-flight connect is cancelled (i.e. the
+/// socket is closed) before the error is returned.
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn tcp_connect_timeout(addr: SocketAddr, dur: Duration)
+    -> ConnectTimeout
+{
+    ConnectTimeout {
+        connect: Some(tcp_connect(addr)),
+        timeout: timeout(dur),
+    }
+}
+
+/// Register a standard library TCP listener on the current loop
+///
+/// This is useful for sharing a single listening socket between loops
+/// running in multiple threads:
+///
+/// ```ignore
+/// let socket = std::net::TcpListener::bind(addr)?;
+/// for _ in 0..threads {
+///     let socket = socket.try_clone()?;
+///     thread::spawn(move || run(|| {
+///         tcp_from_std(socket)?.incoming().for_each(handle_connection)
+///     }));
+/// }
+/// ```
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn tcp_from_std(listener: net::TcpListener) -> io::Result<TcpListener> {
+    let addr = listener.local_addr()?;
+    HANDLE.with(|handle| TcpListener::from_listener(listener, &addr, handle))
+}
+
+/// Create a UDP socket bound to the address on the current loop
+///
+/// This is a shortcut for:
+///
+/// ```ignore
+/// UdpSocket::bind(&addr, &handle())
+/// ```
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn udp_bind(addr: SocketAddr) -> io::Result<UdpSocket> {
+    HANDLE.with(|handle| UdpSocket::bind(&addr, handle))
+}
+
+/// Register a standard library UDP socket on the current loop
+///
+/// This is useful for a socket created with `SO_REUSEPORT` (or cloned)
+/// for serving it from loops in multiple threads, similarly to
+/// `tcp_from_std()`.
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn udp_from_std(socket: net::UdpSocket) -> io::Result<UdpSocket> {
+    HANDLE.with(|handle| UdpSocket::from_socket(socket, handle))
+}
+
+impl Future for ConnectTimeout {
+    type Item = TcpStream;
+    type Error = ConnectError;
+    fn poll(&mut self) -> Poll<TcpStream, ConnectError> {
+        let connect = match self.connect {
+            Some(ref mut connect) => connect,
+            None => return Err(ConnectError::TimedOut),
+        };
+        match connect.poll() {
+            Ok(Async::Ready(sock)) => return Ok(Async::Ready(sock)),
+            Ok(Async::NotReady) => {}
+            Err(e) => return Err(ConnectError::Io(e)),
+        }
+        match self.timeout.poll() {
+            Ok(Async::Ready(())) => {
+                self.connect = None;
+                Err(ConnectError::TimedOut)
+            }
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(e) => panic!("timer failed: {}", e),
+        }
+    }
+}
+
+impl fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConnectError::TimedOut => f.write_str("connection timed out"),
+            ConnectError::Io(ref e) => write!(f, "connection error: {}", e),
+        }
+    }
+}
+
+impl Error for ConnectError {}
+
+impl From<ConnectError> for io::Error {
+    fn from(e: ConnectError) -> io::Error {
+        match e {
+            ConnectError::TimedOut => io::ErrorKind::TimedOut.into(),
+            ConnectError::Io(e) => e,
+        }
+    }
+}
@@ -0,0 +1,293 @@
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::error::Error;
+use std::fmt::{self, Debug};
+use std::panic::{self, catch_unwind, UnwindSafe};
+use std::sync::Once;
+use std::thread;
+use std::time::Duration;
+
+use futures::{self, Future, IntoFuture};
+
+use {HANDLE, run, spawn, with_timeout, DeadlineError};
+use driver::loop_name;
+use metrics::Counted;
+
+
+type PanicHook = Box<dyn Fn(Box<dyn Any + Send>)>;
+
+thread_local! {
+    static PANIC_HOOK: RefCell<Option<PanicHook>> = const { RefCell::new(None) };
+    static CAPTURING: Cell<bool> = const { Cell::new(false) };
+    static PANIC_LOCATION: RefCell<Option<String>> = const { RefCell::new(None) };
+    static PANICKED_TASK: Cell<Option<&'static str>> = const { Cell::new(None) };
+}
+
+static LOCATION_HOOK: Once = Once::new();
+
+/// Error returned by `run_caught()` and `run_caught_timeout()`
+#[derive(Debug, PartialEq, Eq)]
+pub enum RunError<E> {
+    /// The future returned by the initialization function failed
+    Init(E),
+    /// The loop panicked
+    Panic {
+        /// Panic message (if payload is a string)
+        message: String,
+        /// Source location of the panic, like `src/main.rs:10:5`
+        location: Option<String>,
+    },
+    /// The future was not finished in time (`run_caught_timeout()` only)
+    Timeout,
+}
+
+/// Returns panic message if payload is a string (which is true for the
+/// most panics)
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s
+    } else {
+        "<non-string panic payload>"
+    }
+}
+
+/// Marks the panic which is unwinding as the one of the named task, so it's
+/// reported with the name by `report_panic()`
+pub(crate) fn set_panicked_task(name: &'static str) {
+    PANICKED_TASK.with(|t| t.set(Some(name)));
+}
+
+pub(crate) fn report_panic(payload: Box<dyn Any + Send>) {
+    PANIC_HOOK.with(|hook| {
+        match *hook.borrow() {
+            Some(ref hook) => hook(payload),
+            None => {
+                let task = match panicked_task() {
+                    Some(name) => format!("task {:?}", name),
+                    None => String::from("spawned future"),
+                };
+                match loop_name() {
+                    Some(lp) => {
+                        eprintln!("tk-easyloop: {} in loop {:?} \
+                            panicked: {}", task, lp, panic_message(&*payload));
+                    }
+                    None => {
+                        eprintln!("tk-easyloop: {} panicked: {}",
+                            task, panic_message(&*payload));
+                    }
+                }
+            }
+        }
+    });
+    PANICKED_TASK.with(|t| t.set(None));
+}
+
+/// Returns the name of the task which panic is being reported
+///
+/// This is useful in the hook set by `set_panic_hook()`. Returns `None`
+/// outside of the hook or if the panicked future wasn't spawned by
+/// `spawn_named()` (or `spawn_detached()`).
+pub fn panicked_task() -> Option<&'static str> {
+    PANICKED_TASK.with(|t| t.get())
+}
+
+/// Set a function that is called when future spawned by `spawn_catch()`
+/// panics
+///
+/// The hook receives panic payload. By default, panic message is printed
+/// to stderr. The hook is set for the current thread only. The hook is also
+/// called for the panics caught by `Loop::catch_panics()`, the name of the
+/// panicked task is returned by `panicked_task()`.
+pub fn set_panic_hook(hook: Box<dyn Fn(Box<dyn Any + Send>)>) {
+    PANIC_HOOK.with(|h| *h.borrow_mut() = Some(hook));
+}
+
+/// Spawn a future to the current main loop, catching panics
+///
+/// When the future panics, panic is passed to the hook set by
+/// `set_panic_hook()` instead of unwinding through the main loop. If the
+/// future isn't `UnwindSafe` wrap it into `AssertUnwindSafe`.
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn spawn_catch<F>(f: F)
+    where F: Future<Item=(), Error=()> + UnwindSafe + 'static
+{
+    let f = f.catch_unwind().then(|result| {
+        match result {
+            Ok(_) => {}
+            Err(payload) => report_panic(payload),
+        }
+        Ok(())
+    });
+    HANDLE.with(|handle| handle.spawn(Counted::new(f)))
+}
+
+/// Spawn a future to the current main loop, panicking if it fails
+///
+/// This is the opposite of `spawn_log()`: the error of the future is
+/// treated as a programming error, so it crashes the loop loudly instead
+/// of being discarded. This is useful in tests and tools:
+///
+/// ```
+/// # extern crate futures;
+/// # extern crate tk_easyloop;
+/// # use std::time::Duration;
+/// # use tk_easyloop::{run_supervised, spawn_or_panic, sleep};
+/// # fn main() {
+/// let result = run_supervised(|| {
+///     spawn_or_panic(futures::failed::<(), _>("broken invariant"));
+///     sleep(Duration::new(10, 0))
+/// });
+/// assert!(result.is_err());
+/// # }
+/// ```
+///
+/// The panic message contains the debug-formatted error. The panic
+/// unwinds through `run()` (and is returned as `Err` by `run_supervised()`,
+/// as shown above), unless the loop catches panics of spawned futures
+/// (`Loop::catch_panics()`), in which case only the task is stopped and
+/// the panic is passed to the hook set by `set_panic_hook()`.
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn spawn_or_panic<F>(f: F)
+    where F: Future<Item=()> + 'static,
+          F::Error: Debug + 'static,
+{
+    spawn(f.map_err(|e| panic!("spawned future failed: {:?}", e)));
+}
+
+/// Run the main loop like `run()`, catching panics
+///
+/// A panic in the loop (either in the main future or in a spawned one)
+/// stops the loop and is returned as `Err` with the panic payload. This is
+/// useful for supervisors which restart crashed loop threads:
+///
+/// ```ignore
+/// thread::spawn(|| loop {
+///     match run_supervised(|| serve()) {
+///         Ok(_) => break,
+///         Err(_) => eprintln!("Loop crashed, restarting"),
+///     }
+/// });
+/// ```
+///
+/// The function must be `UnwindSafe`, wrap it into `AssertUnwindSafe` if
+/// it isn't (the state it shares with the outer code may be broken after
+/// a panic). All the futures spawned on the loop are dropped on panic.
+pub fn run_supervised<F, R>(f: F) -> thread::Result<Result<R::Item, R::Error>>
+    where F: FnOnce() -> R + UnwindSafe,
+          R: IntoFuture,
+{
+    catch_unwind(move || run(f))
+}
+
+/// Installs a process-wide panic hook recording the location of the panic
+/// if it's inside of `run_caught()` (the hook is chained with the previous
+/// one, so panics are still printed as usual)
+fn install_location_hook() {
+    LOCATION_HOOK.call_once(|| {
+        let prev = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            CAPTURING.try_with(|capturing| {
+                if capturing.get() {
+                    PANIC_LOCATION.with(|loc| {
+                        let mut loc = loc.borrow_mut();
+                        if loc.is_none() {
+                            *loc = info.location().map(|l| l.to_string());
+                        }
+                    });
+                }
+            }).ok();
+            prev(info);
+        }));
+    });
+}
+
+fn caught<F, T, E>(f: F) -> Result<T, RunError<E>>
+    where F: FnOnce() -> Result<T, RunError<E>> + UnwindSafe,
+{
+    install_location_hook();
+    let was_capturing = CAPTURING.with(|c| c.replace(true));
+    let result = catch_unwind(f);
+    CAPTURING.with(|c| c.set(was_capturing));
+    let location = PANIC_LOCATION.with(|loc| loc.borrow_mut().take());
+    match result {
+        Ok(result) => result,
+        Err(payload) => Err(RunError::Panic {
+            message: panic_message(&*payload).to_string(),
+            location,
+        }),
+    }
+}
+
+/// Run the main loop like `run()`, converting panics into errors
+///
+/// This is like `run_supervised()`, but returns a structured error, so
+/// tooling can distinguish normal errors from panics without inspecting
+/// panic payloads:
+///
+/// ```
+/// # extern crate futures;
+/// # extern crate tk_easyloop;
+/// # use tk_easyloop::{run_caught, RunError};
+/// # fn main() {
+/// let err = run_caught(|| futures::failed::<(), _>("bad input"));
+/// assert_eq!(err, Err(RunError::Init("bad input")));
+///
+/// match run_caught(|| -> Result<(), ()> { panic!("broken") }) {
+///     Err(RunError::Panic { message, location }) => {
+///         assert_eq!(message, "broken");
+///         assert!(location.is_some());
+///     }
+///     _ => unreachable!(),
+/// }
+/// # }
+/// ```
+///
+/// To capture the panic location a process-wide panic hook is installed
+/// on the first call. The hook calls the previously installed one, so
+/// panics are still printed as usual.
+pub fn run_caught<F, R>(f: F) -> Result<R::Item, RunError<R::Error>>
+    where F: FnOnce() -> R + UnwindSafe,
+          R: IntoFuture,
+{
+    caught(move || run(f).map_err(RunError::Init))
+}
+
+/// Run the main loop like `run_caught()` and fail with `RunError::Timeout`
+/// if the future is not finished in `dur`
+pub fn run_caught_timeout<F, R>(dur: Duration, f: F)
+    -> Result<R::Item, RunError<R::Error>>
+    where F: FnOnce() -> R + UnwindSafe,
+          R: IntoFuture,
+{
+    caught(move || {
+        run(|| with_timeout(futures::lazy(f), dur)).map_err(|e| match e {
+            DeadlineError::Elapsed => RunError::Timeout,
+            DeadlineError::Inner(e) => RunError::Init(e),
+        })
+    })
+}
+
+impl<E: fmt::Display> fmt::Display for RunError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RunError::Init(ref e) => e.fmt(f),
+            RunError::Panic { ref message, location: Some(ref loc) }
+            => write!(f, "loop panicked at {}: {}", loc, message),
+            RunError::Panic { ref message, location: None }
+            => write!(f, "loop panicked: {}", message),
+            RunError::Timeout => f.write_str("loop timed out"),
+        }
+    }
+}
+
+impl<E: Error> Error for RunError<E> {}
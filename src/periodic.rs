@@ -0,0 +1,107 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::Duration;
+
+use futures::{Future, Stream, Async, Poll};
+use futures::task::{self, Task};
+use tokio_core::reactor::Interval;
+
+use {interval, spawn};
+
+
+struct Shared {
+    cancelled: Cell<bool>,
+    task: RefCell<Option<Task>>,
+}
+
+/// A handle returned by `on_interval()` which allows to stop the callback
+///
+/// By default dropping the handle doesn't stop the callback, use
+/// `auto_cancel(true)` to stop it on drop.
+pub struct IntervalHandle {
+    shared: Rc<Shared>,
+    auto_cancel: bool,
+}
+
+struct Periodic<F> {
+    interval: Interval,
+    callback: F,
+    shared: Rc<Shared>,
+}
+
+/// Call a function every `dur` until cancelled
+///
+/// This is similar to `repeat()` but returns a handle that can be used to
+/// stop calling the function, or to stop it automatically when the handle
+/// is dropped:
+///
+/// ```ignore
+/// struct Cache {
+///     eviction: IntervalHandle,
+/// }
+/// let eviction = on_interval(Duration::new(60, 0), || evict_old())
+///     .auto_cancel(true);
+/// ```
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn on_interval<F>(dur: Duration, f: F) -> IntervalHandle
+    where F: FnMut() + 'static
+{
+    let shared = Rc::new(Shared {
+        cancelled: Cell::new(false),
+        task: RefCell::new(None),
+    });
+    spawn(Periodic {
+        interval: interval(dur),
+        callback: f,
+        shared: shared.clone(),
+    });
+    IntervalHandle { shared, auto_cancel: false }
+}
+
+impl IntervalHandle {
+    /// Stop calling the function
+    ///
+    /// The task is removed from the loop at the next loop iteration.
+    pub fn cancel(&self) {
+        self.shared.cancelled.set(true);
+        if let Some(task) = self.shared.task.borrow_mut().take() {
+            task.notify();
+        }
+    }
+    /// Whether to cancel the callback when the handle is dropped
+    pub fn auto_cancel(mut self, value: bool) -> IntervalHandle {
+        self.auto_cancel = value;
+        self
+    }
+}
+
+impl Drop for IntervalHandle {
+    fn drop(&mut self) {
+        if self.auto_cancel {
+            self.cancel();
+        }
+    }
+}
+
+impl<F: FnMut()> Future for Periodic<F> {
+    type Item = ();
+    type Error = ();
+    fn poll(&mut self) -> Poll<(), ()> {
+        loop {
+            if self.shared.cancelled.get() {
+                return Ok(Async::Ready(()));
+            }
+            *self.shared.task.borrow_mut() = Some(task::current());
+            match self.interval.poll() {
+                Ok(Async::Ready(Some(()))) => (self.callback)(),
+                Ok(Async::Ready(None)) => return Ok(Async::Ready(())),
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Err(e) => panic!("timer failed: {}", e),
+            }
+        }
+    }
+}
@@ -0,0 +1,316 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::SocketAddr;
+use std::ops::{Deref, DerefMut};
+use std::rc::{Rc, Weak};
+use std::time::{Duration, Instant};
+
+use futures::{Future, Async, Poll};
+use tokio_core::net::TcpStream;
+use tokio_io::{AsyncRead, AsyncWrite};
+
+use {now, tcp_connect_timeout, ConnectTimeout};
+
+
+loop_local! {
+    static POOL: Rc<Inner> = Rc::new(Inner {
+        idle: RefCell::new(HashMap::new()),
+    });
+}
+
+struct Inner {
+    idle: RefCell<HashMap<SocketAddr, Vec<(TcpStream, Instant)>>>,
+}
+
+/// Settings of the `ConnectionPool`
+#[derive(Debug, Clone, Copy)]
+pub struct PoolConfig {
+    max_idle_per_host: usize,
+    idle_timeout: Duration,
+    connect_timeout: Duration,
+}
+
+/// A pool of TCP connections of the current loop, returned by `pool()`
+///
+/// This is a cheap handle, all the handles of the same loop share the
+/// same connections.
+#[derive(Clone)]
+pub struct ConnectionPool {
+    inner: Rc<Inner>,
+    config: PoolConfig,
+}
+
+/// A future returned by `ConnectionPool::get()`
+pub struct PoolGet {
+    state: GetState,
+}
+
+enum GetState {
+    Ready(Option<PooledConnection>),
+    Connecting(SocketAddr, Weak<Inner>, PoolConfig, ConnectTimeout),
+}
+
+/// A connection borrowed from the `ConnectionPool`
+///
+/// Dereferences to `TcpStream`. The connection is returned to the pool
+/// when dropped, unless `discard()` is called, or the connection was shut
+/// down, reached end of file or failed with an I/O error.
+pub struct PooledConnection {
+    addr: SocketAddr,
+    stream: Option<TcpStream>,
+    pool: Weak<Inner>,
+    config: PoolConfig,
+    discarded: bool,
+}
+
+/// Returns the connection pool of the current loop
+///
+/// The idle connections are stored in loop-local storage, so they are
+/// shared by all the handles of the loop, and are closed when the loop
+/// exits. The `config` applies to the connections borrowed via this
+/// handle. Connections are opened lazily and reused after they're dropped:
+///
+/// ```
+/// # extern crate futures;
+/// # extern crate tk_easyloop;
+/// # extern crate tokio_io;
+/// # use std::net::TcpListener;
+/// # use std::thread;
+/// use futures::Future;
+/// use tokio_io::AsyncWrite;
+/// use tk_easyloop::{run, pool, PoolConfig};
+///
+/// # fn main() {
+/// # let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+/// # let addr = listener.local_addr().unwrap();
+/// # thread::spawn(move || {
+/// #     let mut accepted = Vec::new();
+/// #     for conn in listener.incoming() {
+/// #         accepted.push(conn.unwrap());
+/// #     }
+/// # });
+/// run(|| {
+///     let pool = pool(PoolConfig::new().max_idle_per_host(4));
+///     pool.clone().get(addr).and_then(move |conn| {
+///         let port = conn.local_addr().unwrap().port();
+///         drop(conn);
+///         assert_eq!(pool.idle_connections(), 1);
+///         pool.get(addr).map(move |mut conn| {
+///             // the same connection is reused
+///             assert_eq!(conn.local_addr().unwrap().port(), port);
+///             assert_eq!(pool.idle_connections(), 0);
+///             // the connection that was shut down is not returned
+///             AsyncWrite::shutdown(&mut conn).unwrap();
+///             drop(conn);
+///             assert_eq!(pool.idle_connections(), 0);
+///         })
+///     })
+/// }).unwrap();
+/// # }
+/// ```
+///
+/// The pool doesn't know whether the idle connection is closed by the
+/// peer, so requests should be retried on a fresh connection if they fail
+/// on a reused one. Call `PooledConnection::discard()` for connections in
+/// a bad state (e.g. where a response wasn't fully read).
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn pool(config: PoolConfig) -> ConnectionPool {
+    ConnectionPool {
+        inner: POOL.with(|inner| inner.clone()),
+        config,
+    }
+}
+
+impl PoolConfig {
+    /// Create the default config
+    ///
+    /// By default up to 8 idle connections per address are kept for 90
+    /// seconds, and connect timeout is 10 seconds.
+    pub fn new() -> PoolConfig {
+        PoolConfig {
+            max_idle_per_host: 8,
+            idle_timeout: Duration::new(90, 0),
+            connect_timeout: Duration::new(10, 0),
+        }
+    }
+    /// Maximum number of idle connections kept for each address
+    pub fn max_idle_per_host(mut self, value: usize) -> PoolConfig {
+        self.max_idle_per_host = value;
+        self
+    }
+    /// Close connections which were not used for this time
+    pub fn idle_timeout(mut self, value: Duration) -> PoolConfig {
+        self.idle_timeout = value;
+        self
+    }
+    /// Timeout of establishing a new connection
+    pub fn connect_timeout(mut self, value: Duration) -> PoolConfig {
+        self.connect_timeout = value;
+        self
+    }
+}
+
+impl Default for PoolConfig {
+    fn default() -> PoolConfig {
+        PoolConfig::new()
+    }
+}
+
+impl ConnectionPool {
+    /// Get a connection to the address
+    ///
+    /// Returns an idle connection if there is one, or opens a new one.
+    /// Connect errors (including `TimedOut`) are returned as `io::Error`.
+    pub fn get(&self, addr: SocketAddr) -> PoolGet {
+        let config = self.config;
+        let time = now();
+        let mut idle = self.inner.idle.borrow_mut();
+        if let Some(conns) = idle.get_mut(&addr) {
+            conns.retain(|&(_, since)| {
+                time.saturating_duration_since(since) < config.idle_timeout
+            });
+            if let Some((stream, _)) = conns.pop() {
+                return PoolGet {
+                    state: GetState::Ready(Some(PooledConnection {
+                        addr,
+                        stream: Some(stream),
+                        pool: Rc::downgrade(&self.inner),
+                        config,
+                        discarded: false,
+                    })),
+                };
+            }
+        }
+        PoolGet {
+            state: GetState::Connecting(addr, Rc::downgrade(&self.inner),
+                config, tcp_connect_timeout(addr, config.connect_timeout)),
+        }
+    }
+    /// Returns number of idle connections in the pool
+    pub fn idle_connections(&self) -> usize {
+        self.inner.idle.borrow().values().map(|c| c.len()).sum()
+    }
+    /// Close all idle connections
+    pub fn clear(&self) {
+        self.inner.idle.borrow_mut().clear();
+    }
+}
+
+impl PooledConnection {
+    /// Returns the address this connection is connected to
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+    /// Close the connection instead of returning it to the pool
+    pub fn discard(mut self) {
+        self.stream.take();
+    }
+    /// Take the stream, so it isn't returned to the pool
+    pub fn into_inner(mut self) -> TcpStream {
+        self.stream.take().expect("stream is not taken")
+    }
+    fn check<T>(&mut self, result: io::Result<T>) -> io::Result<T> {
+        match result {
+            Err(ref e) if e.kind() != io::ErrorKind::WouldBlock => {
+                self.discarded = true;
+            }
+            _ => {}
+        }
+        result
+    }
+}
+
+impl Future for PoolGet {
+    type Item = PooledConnection;
+    type Error = io::Error;
+    fn poll(&mut self) -> Poll<PooledConnection, io::Error> {
+        match self.state {
+            GetState::Ready(ref mut conn) => {
+                Ok(Async::Ready(conn.take().expect("future polled twice")))
+            }
+            GetState::Connecting(addr, ref pool, config, ref mut connect) => {
+                let stream = match connect.poll() {
+                    Ok(Async::Ready(stream)) => stream,
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Err(e) => return Err(e.into()),
+                };
+                Ok(Async::Ready(PooledConnection {
+                    addr,
+                    stream: Some(stream),
+                    pool: pool.clone(),
+                    config,
+                    discarded: false,
+                }))
+            }
+        }
+    }
+}
+
+impl Deref for PooledConnection {
+    type Target = TcpStream;
+    fn deref(&self) -> &TcpStream {
+        self.stream.as_ref().expect("stream is not taken")
+    }
+}
+
+impl DerefMut for PooledConnection {
+    fn deref_mut(&mut self) -> &mut TcpStream {
+        self.stream.as_mut().expect("stream is not taken")
+    }
+}
+
+impl Read for PooledConnection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let result = (**self).read(buf);
+        if let Ok(0) = result {
+            if !buf.is_empty() {
+                self.discarded = true;
+            }
+        }
+        self.check(result)
+    }
+}
+
+impl Write for PooledConnection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let result = (**self).write(buf);
+        self.check(result)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        let result = (**self).flush();
+        self.check(result)
+    }
+}
+
+impl AsyncRead for PooledConnection {}
+
+impl AsyncWrite for PooledConnection {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.discarded = true;
+        AsyncWrite::shutdown(&mut **self)
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        if self.discarded {
+            return;
+        }
+        let (stream, pool) = match (self.stream.take(), self.pool.upgrade()) {
+            (Some(stream), Some(pool)) => (stream, pool),
+            // taken by `into_inner()`, or the loop has exited
+            _ => return,
+        };
+        let max_idle = self.config.max_idle_per_host;
+        let mut idle = pool.idle.borrow_mut();
+        let conns = idle.entry(self.addr).or_insert_with(Vec::new);
+        if conns.len() < max_idle {
+            conns.push((stream, now()));
+        }
+    }
+}
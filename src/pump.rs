@@ -0,0 +1,105 @@
+use std::error::Error;
+use std::fmt;
+
+use futures::{Future, Stream, Sink};
+use futures::sync::{mpsc, oneshot};
+
+use log_errors::log_error;
+use spawn;
+
+
+/// Error returned by `pump()`
+#[derive(Debug, PartialEq, Eq)]
+pub enum PumpError<S, K> {
+    /// The stream returned an error
+    Stream(S),
+    /// The sink returned an error
+    Sink(K),
+}
+
+type PumpResult<S, K> = Result<(), PumpError<S, K>>;
+
+/// Spawn a future that forwards all items of the stream into the sink and
+/// return a receiver for the result
+///
+/// This is like `spawn_forward()` but the caller can wait for forwarding to
+/// finish and see which side failed:
+///
+/// ```ignore
+/// pump(read_records(file), upload_sink)
+/// .map_err(|_| unreachable!())
+/// .and_then(|result| match result {
+///     Ok(()) => Ok(println!("All records uploaded")),
+///     Err(PumpError::Stream(e)) => Err(format!("read error: {}", e)),
+///     Err(PumpError::Sink(e)) => Err(format!("upload error: {}", e)),
+/// })
+/// ```
+///
+/// The receiver fails with `Canceled` only if the loop is stopped before
+/// forwarding is finished.
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn pump<S, K>(stream: S, sink: K)
+    -> oneshot::Receiver<PumpResult<S::Error, K::SinkError>>
+    where S: Stream + 'static,
+          K: Sink<SinkItem=S::Item> + 'static,
+{
+    let (tx, rx) = oneshot::channel();
+    spawn(stream.map_err(PumpError::Stream)
+        .forward(sink.sink_map_err(PumpError::Sink))
+        .then(move |result| {
+            tx.send(result.map(|_| ())).ok();
+            Ok(())
+        }));
+    rx
+}
+
+/// Spawn each future to the current main loop and return a channel
+/// receiving their items in the order of completion
+///
+/// This is better for latency than `join_all()`, as results can be
+/// processed as soon as they are ready:
+///
+/// ```ignore
+/// spawn_to_channel(shards.iter().map(|s| s.query(&q)).collect())
+/// .for_each(|rows| {
+///     output.extend(rows);
+///     Ok(())
+/// })
+/// ```
+///
+/// Errors are passed to the function set by `set_error_logger()`. The
+/// stream ends when all the futures are finished.
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn spawn_to_channel<F, T>(futures: Vec<F>) -> mpsc::Receiver<T>
+    where F: Future<Item=T> + 'static,
+          F::Error: fmt::Display,
+          T: 'static,
+{
+    // every sender has a guaranteed slot, so sending never waits
+    let (tx, rx) = mpsc::channel(0);
+    for f in futures {
+        let tx = tx.clone();
+        spawn(f.map_err(|e| log_error(&e))
+            .and_then(move |item| tx.send(item).map(|_| ()).map_err(|_| ())));
+    }
+    rx
+}
+
+impl<S: fmt::Display, K: fmt::Display> fmt::Display for PumpError<S, K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PumpError::Stream(ref e) => write!(f, "stream error: {}", e),
+            PumpError::Sink(ref e) => write!(f, "sink error: {}", e),
+        }
+    }
+}
+
+impl<S: Error, K: Error> Error for PumpError<S, K> {}
@@ -0,0 +1,56 @@
+use futures::{Future, Async, Poll};
+use futures::future::{SelectAll, select_all};
+
+
+/// A future returned by `race()`
+pub struct Race<F: Future> {
+    inner: SelectAll<F>,
+}
+
+/// Create a future resolving to the result of the first future to finish
+///
+/// This is useful to run redundant requests (e.g. to multiple DNS
+/// servers) and use the fastest response:
+///
+/// ```
+/// # extern crate futures;
+/// # extern crate tk_easyloop;
+/// # use std::time::Duration;
+/// # use futures::Future;
+/// # use tk_easyloop::{run, race, timeout};
+/// # fn main() {
+/// let winner = run(|| race(vec![50, 10].into_iter().map(|ms| {
+///     timeout(Duration::from_millis(ms)).map(move |()| ms)
+/// }))).unwrap();
+/// assert_eq!(winner, 10);
+/// # }
+/// ```
+///
+/// Both success and error count as finishing, so the first error is
+/// returned even if other futures could succeed later.
+///
+/// **Note:** when the first future is finished, all other futures are
+/// dropped. So if the futures have side effects (e.g. a request is sent
+/// but the response is not read yet), they may be observed partially done.
+///
+/// # Panics
+///
+/// If the iterator is empty
+pub fn race<I>(iter: I) -> Race<I::Item>
+    where I: IntoIterator,
+          I::Item: Future,
+{
+    Race { inner: select_all(iter) }
+}
+
+impl<F: Future> Future for Race<F> {
+    type Item = F::Item;
+    type Error = F::Error;
+    fn poll(&mut self) -> Poll<F::Item, F::Error> {
+        match self.inner.poll() {
+            Ok(Async::Ready((value, _, _rest))) => Ok(Async::Ready(value)),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err((e, _, _rest)) => Err(e),
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+use futures::{Future, Async, Poll};
+
+use {spawn, sleep, now, Sleep};
+
+
+thread_local! {
+    static RATE: Cell<Option<Rate>> = const { Cell::new(None) };
+}
+
+#[derive(Clone, Copy)]
+struct Rate {
+    per_sec: f64,
+    burst: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+/// A future returned by `spawn_rate_limited()`
+pub struct RateLimited<F> {
+    future: Option<F>,
+    sleep: Option<Sleep>,
+}
+
+/// Set the rate of futures spawned by `spawn_rate_limited()` in the
+/// current thread
+///
+/// Up to `burst` futures can be spawned at once, and then `per_sec`
+/// futures per second. By default there is no limit. Setting the rate
+/// refills the bucket.
+///
+/// # Panics
+///
+/// If `per_sec` or `burst` is zero.
+pub fn set_spawn_rate(per_sec: u32, burst: u32) {
+    assert!(per_sec > 0 && burst > 0, "spawn rate must be non-zero");
+    RATE.with(|r| r.set(Some(Rate {
+        per_sec: per_sec as f64,
+        burst: burst as f64,
+        tokens: burst as f64,
+        updated: now(),
+    })));
+}
+
+/// Spawn a future to the current main loop when the rate limit allows
+///
+/// Returns a future which resolves when the future is spawned. This is
+/// useful to respect the limits of downstream services:
+///
+/// ```ignore
+/// set_spawn_rate(100, 10);
+/// stream::iter_ok(urls).for_each(|url| spawn_rate_limited(fetch(url)))
+/// ```
+///
+/// Unlike `spawn_bounded()` this limits how often futures are started,
+/// not how many of them run at the same time. Rate is set by
+/// `set_spawn_rate()`, without it the future is spawned immediately.
+///
+/// Futures are spawned in order, the burst is spawned without waiting:
+///
+/// ```
+/// # extern crate futures;
+/// # extern crate tk_easyloop;
+/// # use std::time::{Duration, Instant};
+/// # use futures::{Future, Stream};
+/// # use futures::future::ok;
+/// # use futures::stream::iter_ok;
+/// # use tk_easyloop::{run, set_spawn_rate, spawn_rate_limited};
+/// # fn main() {
+/// let started = Instant::now();
+/// let spawned = run(|| {
+///     set_spawn_rate(20, 2);
+///     iter_ok::<_, ()>(0..4).and_then(|i| {
+///         spawn_rate_limited(ok(())).map(move |()| i)
+///     }).collect()
+/// }).unwrap();
+/// assert_eq!(spawned, [0, 1, 2, 3]);
+/// // two of the futures waited for a token for 50 ms each
+/// assert!(started.elapsed() >= Duration::from_millis(100));
+/// # }
+/// ```
+///
+/// # Panics
+///
+/// The returned future panics if there is no currently running loop (i.e.
+/// it's not polled from the inside of `run()`.
+pub fn spawn_rate_limited<F>(f: F) -> RateLimited<F>
+    where F: Future<Item=(), Error=()> + 'static
+{
+    RateLimited { future: Some(f), sleep: None }
+}
+
+/// Takes a token from the bucket, or returns time until the next one
+fn take_token() -> Result<(), Duration> {
+    RATE.with(|cell| {
+        let mut rate = match cell.get() {
+            Some(rate) => rate,
+            None => return Ok(()),
+        };
+        let time = now();
+        let elapsed = time.saturating_duration_since(rate.updated);
+        rate.tokens = (rate.tokens + elapsed.as_secs_f64() * rate.per_sec)
+            .min(rate.burst);
+        rate.updated = time;
+        let result = if rate.tokens >= 1.0 {
+            rate.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - rate.tokens) / rate.per_sec))
+        };
+        cell.set(Some(rate));
+        result
+    })
+}
+
+impl<F: Future<Item=(), Error=()> + 'static> Future for RateLimited<F> {
+    type Item = ();
+    type Error = ();
+    fn poll(&mut self) -> Poll<(), ()> {
+        loop {
+            if let Some(ref mut sleep) = self.sleep {
+                if sleep.poll()?.is_not_ready() {
+                    return Ok(Async::NotReady);
+                }
+            }
+            match take_token() {
+                Ok(()) => {
+                    let f = self.future.take()
+                        .expect("future polled after completion");
+                    spawn(f);
+                    return Ok(Async::Ready(()));
+                }
+                Err(wait) => self.sleep = Some(sleep(wait)),
+            }
+        }
+    }
+}
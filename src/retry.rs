@@ -0,0 +1,197 @@
+use std::time::{Duration, Instant};
+
+use futures::{Future, IntoFuture, Async, Poll};
+
+use {now, sleep, Sleep};
+
+
+/// A policy of retrying failed attempts used by `retry()`
+///
+/// The delay after the first failed attempt is `base`, and every next
+/// delay is multiplied by `multiplier` (which is `2.0` by default) up to
+/// the `max_delay`:
+///
+/// ```ignore
+/// Backoff::new(5, Duration::from_millis(100))
+///     .multiplier(1.5)
+///     .max_delay(Duration::new(2, 0))
+///     .max_total(Duration::new(10, 0))
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Backoff {
+    max_attempts: u32,
+    base: Duration,
+    multiplier: f64,
+    max_delay: Duration,
+    max_total: Option<Duration>,
+}
+
+/// A future returned by `retry()` and `retry_if()`
+pub struct Retry<F, R: IntoFuture, P> {
+    policy: Backoff,
+    factory: F,
+    retryable: P,
+    attempt: u32,
+    delay: Duration,
+    // set when the first attempt is started
+    started: Option<Instant>,
+    state: State<R::Future>,
+}
+
+type Always<E> = fn(&E) -> bool;
+
+enum State<F> {
+    Start,
+    Running(F),
+    Waiting(Sleep),
+}
+
+impl Backoff {
+    /// Create a policy allowing `max_attempts` attempts in total with the
+    /// specified delay after the first failure
+    pub fn new(max_attempts: u32, base: Duration) -> Backoff {
+        Backoff {
+            max_attempts,
+            base,
+            multiplier: 2.0,
+            // effectively unlimited, but still fits `Instant` arithmetic
+            max_delay: Duration::new(u32::MAX as u64, 0),
+            max_total: None,
+        }
+    }
+    /// Set the factor by which the delay grows after each failed attempt
+    ///
+    /// # Panics
+    ///
+    /// When `value` is not finite or is less than `1.0`
+    pub fn multiplier(mut self, value: f64) -> Backoff {
+        assert!(value.is_finite() && value >= 1.0,
+            "backoff multiplier must be finite and at least 1.0");
+        self.multiplier = value;
+        self
+    }
+    /// Limit the delay between attempts
+    ///
+    /// This also applies to the `base` delay.
+    pub fn max_delay(mut self, value: Duration) -> Backoff {
+        self.max_delay = value;
+        self
+    }
+    /// Don't start an attempt if it would begin later than `value` after
+    /// the first attempt
+    pub fn max_total(mut self, value: Duration) -> Backoff {
+        self.max_total = Some(value);
+        self
+    }
+    fn next_delay(&self, delay: Duration) -> Duration {
+        let secs = delay.as_secs_f64() * self.multiplier;
+        // compared as floats, so huge (or rounded up) values don't panic
+        // when converted back to `Duration`
+        if secs >= self.max_delay.as_secs_f64() {
+            self.max_delay
+        } else {
+            Duration::from_secs_f64(secs)
+        }
+    }
+}
+
+/// Retry a future until it succeeds using the backoff policy
+///
+/// The function `f` is called to create each attempt. Between failed
+/// attempts the future waits using `sleep()` on the current loop. When
+/// attempts are exhausted the last error is returned:
+///
+/// ```ignore
+/// retry(Backoff::new(5, Duration::from_millis(100)),
+///       || tcp_connect(addr))
+/// ```
+///
+/// # Panics
+///
+/// The future panics if it's polled when no loop is running
+pub fn retry<F, R>(policy: Backoff, f: F)
+    -> Retry<F, R, Always<R::Error>>
+    where F: FnMut() -> R,
+          R: IntoFuture,
+{
+    fn always<E>(_: &E) -> bool { true }
+    retry_if(policy, f, always)
+}
+
+/// Retry a future using the backoff policy while `retryable` returns
+/// `true` for the error
+///
+/// This is like `retry()` but errors for which `retryable` returns `false`
+/// (for example, authentication errors) are returned immediately.
+///
+/// Like with every future, nothing happens until the future is polled:
+/// the first attempt is created on the first poll.
+///
+/// # Panics
+///
+/// The future panics if it's polled when no loop is running
+pub fn retry_if<F, R, P>(policy: Backoff, f: F, retryable: P)
+    -> Retry<F, R, P>
+    where F: FnMut() -> R,
+          R: IntoFuture,
+          P: FnMut(&R::Error) -> bool,
+{
+    Retry {
+        policy,
+        factory: f,
+        retryable,
+        attempt: 1,
+        delay: policy.base.min(policy.max_delay),
+        started: None,
+        state: State::Start,
+    }
+}
+
+impl<F, R, P> Future for Retry<F, R, P>
+    where F: FnMut() -> R,
+          R: IntoFuture,
+          P: FnMut(&R::Error) -> bool,
+{
+    type Item = R::Item;
+    type Error = R::Error;
+    fn poll(&mut self) -> Poll<R::Item, R::Error> {
+        loop {
+            let next = match self.state {
+                State::Start => {
+                    self.started = Some(now());
+                    State::Running((self.factory)().into_future())
+                }
+                State::Running(ref mut f) => match f.poll() {
+                    Ok(Async::Ready(value)) => return Ok(Async::Ready(value)),
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Err(e) => {
+                        if self.attempt >= self.policy.max_attempts ||
+                            !(self.retryable)(&e)
+                        {
+                            return Err(e);
+                        }
+                        if let (Some(max), Some(started)) =
+                            (self.policy.max_total, self.started)
+                        {
+                            if now() + self.delay > started + max {
+                                return Err(e);
+                            }
+                        }
+                        let delay = self.delay;
+                        self.delay = self.policy.next_delay(delay);
+                        State::Waiting(sleep(delay))
+                    }
+                },
+                State::Waiting(ref mut timer) => match timer.poll() {
+                    Ok(Async::Ready(())) => {
+                        self.attempt += 1;
+                        State::Running((self.factory)().into_future())
+                    }
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Err(()) => unreachable!(),
+                },
+            };
+            self.state = next;
+        }
+    }
+}
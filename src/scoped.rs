@@ -0,0 +1,96 @@
+use std::cell::RefCell;
+
+use futures::{Future, IntoFuture, Async, Poll};
+
+use run;
+
+
+/// A scope for spawning non-`'static` futures, see `run_scoped()`
+pub struct Scope<'a> {
+    futures: RefCell<Vec<Box<dyn Future<Item=(), Error=()> + 'a>>>,
+}
+
+struct Joined<'s, 'a: 's, F: Future> {
+    main: F,
+    result: Option<F::Item>,
+    scope: &'s Scope<'a>,
+}
+
+impl<'a> Scope<'a> {
+    /// Spawn a future which may borrow data from outside of `run_scoped()`
+    ///
+    /// The future runs on the current loop, and `run_scoped()` doesn't
+    /// return until it is finished.
+    pub fn spawn<F>(&self, f: F)
+        where F: Future<Item=(), Error=()> + 'a
+    {
+        self.futures.borrow_mut().push(Box::new(f));
+    }
+}
+
+impl<'s, 'a: 's, F: Future> Future for Joined<'s, 'a, F> {
+    type Item = F::Item;
+    type Error = F::Error;
+    fn poll(&mut self) -> Poll<F::Item, F::Error> {
+        if self.result.is_none() {
+            if let Async::Ready(value) = self.main.poll()? {
+                self.result = Some(value);
+            }
+        }
+        // scoped futures are polled on every wakeup of any of them,
+        // as they are all run within a single task
+        let mut futures = self.scope.futures.borrow_mut();
+        futures.retain_mut(|f| matches!(f.poll(), Ok(Async::NotReady)));
+        if futures.is_empty() {
+            if let Some(value) = self.result.take() {
+                return Ok(Async::Ready(value));
+            }
+        }
+        Ok(Async::NotReady)
+    }
+}
+
+/// Run the main loop with a scope which allows spawning futures that
+/// borrow data from the stack
+///
+/// This is like `run()`, but `f` receives a `Scope` whose `spawn()`
+/// doesn't require futures to be `'static`. The function returns when
+/// both the future returned by `f` and all the scoped futures are
+/// finished (or immediately when the future returned by `f` fails).
+///
+/// ```
+/// # extern crate futures;
+/// # extern crate tk_easyloop;
+/// # use std::cell::Cell;
+/// # use std::time::Duration;
+/// # use futures::Future;
+/// # use tk_easyloop::{run_scoped, sleep};
+/// # fn main() {
+/// let total = Cell::new(0);
+/// let values = vec![1, 2, 3];
+/// run_scoped(|scope| {
+///     for value in &values {
+///         let total = &total;
+///         scope.spawn(sleep(Duration::from_millis(1)).map(move |()| {
+///             total.set(total.get() + value);
+///         }));
+///     }
+///     Ok::<(), ()>(())
+/// }).unwrap();
+/// assert_eq!(total.get(), 6);
+/// # }
+/// ```
+///
+/// Scoped futures are all polled within a single task, so this is
+/// less efficient than `spawn()` for large numbers of futures. Scoped
+/// futures can only be spawned from `f` itself.
+pub fn run_scoped<'a, F, R>(f: F) -> Result<R::Item, R::Error>
+    where F: FnOnce(&Scope<'a>) -> R,
+          R: IntoFuture,
+{
+    let scope = Scope { futures: RefCell::new(Vec::new()) };
+    run(|| {
+        let main = f(&scope).into_future();
+        Joined { main, result: None, scope: &scope }
+    })
+}
@@ -0,0 +1,112 @@
+use std::error::Error;
+use std::fmt;
+
+use futures::{self, Future, IntoFuture};
+use futures::future::Either;
+use futures::sync::oneshot;
+
+use driver::{drive, check_not_nested, new_core, Config};
+use SHUTDOWN;
+
+
+/// The reason why `run_server()` has exited
+#[derive(Debug, PartialEq, Eq)]
+pub enum ServerError<E> {
+    /// The initialization function returned an error
+    Init(E),
+    /// The loop was stopped by `shutdown()`
+    Shutdown,
+    /// The listeners future returned by initialization function has finished
+    ListenersEnded,
+}
+
+/// Run the main loop for a server application
+///
+/// This is like `run_forever()`, but the initialization function returns a
+/// future representing the listeners (e.g. a `join_all()` of the accept
+/// loops) and the return value tells why the server stopped:
+///
+/// ```ignore
+/// match run_server(|| {
+///     let listener = TcpListener::bind(&addr, &handle())?;
+///     Ok(listener.incoming().for_each(handle_connection)
+///        .map_err(|e| error!("Accept error: {}", e)))
+/// }) {
+///     Err(ServerError::Init(e)) => error!("Can't start server: {}", e),
+///     Err(ServerError::Shutdown) => info!("Server stopped"),
+///     Err(ServerError::ListenersEnded) => error!("All listeners closed"),
+///     Ok(()) => unreachable!(),
+/// }
+/// ```
+///
+/// The server never exits normally, so this function always returns an
+/// error, `ServerError::Shutdown` being the clean one.
+pub fn run_server<F, L, E>(f: F) -> Result<(), ServerError<E>>
+    where F: FnOnce() -> Result<L, E>,
+          L: IntoFuture<Item=(), Error=()>,
+{
+    check_not_nested();
+    let (tx, rx) = oneshot::channel();
+    SHUTDOWN.with(|s| *s.borrow_mut() = Some(tx));
+    let mut lp = new_core();
+    let result = drive(&mut lp, &Config::default(), futures::lazy(f)
+        .map_err(ServerError::Init)
+        .and_then(|listeners| {
+            listeners.into_future().select2(rx).then(|res| match res {
+                Ok(Either::A(_)) | Err(Either::A(_))
+                => Err(ServerError::ListenersEnded),
+                Ok(Either::B(_)) | Err(Either::B(_))
+                => Err(ServerError::Shutdown),
+            })
+        }));
+    SHUTDOWN.with(|s| s.borrow_mut().take());
+    result
+}
+
+impl<E: fmt::Display> fmt::Display for ServerError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ServerError::Init(ref e) => write!(f, "initialization error: {}", e),
+            ServerError::Shutdown => f.write_str("shutdown requested"),
+            ServerError::ListenersEnded => f.write_str("all listeners ended"),
+        }
+    }
+}
+
+impl<E: Error> Error for ServerError<E> {}
+
+/// Run the main loop until the future representing the whole server
+/// completes
+///
+/// This suits servers which are a single future (e.g. a `select()` of
+/// listeners) rather than a set of spawned tasks:
+///
+/// ```ignore
+/// run_server_future(|| {
+///     let listener = TcpListener::bind(&addr, &handle()).unwrap();
+///     listener.incoming().for_each(handle_connection)
+///         .select(admin_commands())
+///         .map(|_| ()).map_err(|(e, _)| e)
+/// })
+/// ```
+///
+/// Unlike `run()`, the loop can also be stopped by `shutdown()`, in which
+/// case the server future is dropped and `Ok(())` is returned. Otherwise
+/// the result of the server future is returned.
+pub fn run_server_future<F, R>(f: F) -> Result<(), R::Error>
+    where F: FnOnce() -> R,
+          R: IntoFuture<Item=()>,
+{
+    check_not_nested();
+    let (tx, rx) = oneshot::channel();
+    SHUTDOWN.with(|s| *s.borrow_mut() = Some(tx));
+    let mut lp = new_core();
+    let result = drive(&mut lp, &Config::default(), futures::lazy(f)
+        .select2(rx).then(|res| match res {
+            Ok(Either::A(((), _))) => Ok(()),
+            Err(Either::A((e, _))) => Err(e),
+            Ok(Either::B(_)) | Err(Either::B(_)) => Ok(()),
+        }));
+    SHUTDOWN.with(|s| s.borrow_mut().take());
+    result
+}
@@ -0,0 +1,165 @@
+use std::cell::Cell;
+use std::process;
+use std::rc::Rc;
+use std::sync::{Mutex, Once};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use ctrlc;
+use futures::{Future, Poll};
+use futures::sync::oneshot;
+
+use {run_until, spawn, AbortHandle};
+
+
+static INSTALL: Once = Once::new();
+/// Number of `run_until_ctrlc()` loops which are shutting down
+static FORCE_EXIT: AtomicUsize = AtomicUsize::new(0);
+static LISTENERS: Mutex<Vec<oneshot::Sender<()>>> = Mutex::new(Vec::new());
+
+
+/// A future returned by `ctrl_c()`
+///
+/// Resolves to `()` when the next Ctrl-C (SIGINT) is received.
+pub struct CtrlC {
+    rx: oneshot::Receiver<()>,
+}
+
+/// Decrements `FORCE_EXIT` when `run_until_ctrlc()` returns (or panics)
+struct ForceExit {
+    armed: Rc<Cell<bool>>,
+}
+
+fn on_interrupt() {
+    if FORCE_EXIT.load(Ordering::SeqCst) > 0 {
+        process::exit(130);
+    }
+    let mut delivered = false;
+    for tx in LISTENERS.lock().unwrap().drain(..) {
+        delivered |= tx.send(()).is_ok();
+    }
+    if !delivered {
+        // nobody waits for the signal, so behave like there is no handler
+        process::exit(130);
+    }
+}
+
+/// Create a future that resolves on the next Ctrl-C (SIGINT)
+///
+/// The future may be polled on any loop. The signal handler is installed
+/// on the first call and is never uninstalled, but when there are no live
+/// `CtrlC` futures, Ctrl-C terminates the process with exit code 130 as
+/// if there was no handler.
+///
+/// # Panics
+///
+/// When signal handler can't be installed, in particular if some other
+/// code in the process has already installed a handler via `ctrlc` crate
+pub fn ctrl_c() -> CtrlC {
+    INSTALL.call_once(|| {
+        ctrlc::set_handler(on_interrupt).expect("install ctrl-c handler");
+    });
+    let (tx, rx) = oneshot::channel();
+    let mut listeners = LISTENERS.lock().unwrap();
+    listeners.retain(|tx| !tx.is_canceled());
+    listeners.push(tx);
+    CtrlC { rx }
+}
+
+impl Future for CtrlC {
+    type Item = ();
+    type Error = ();
+    fn poll(&mut self) -> Poll<(), ()> {
+        self.rx.poll().map_err(|_| ())
+    }
+}
+
+/// Run the main loop and initialize it by running a function, which spawns
+/// more futures in the main loop. Then run loop until the Ctrl-C is pressed.
+///
+/// This is basically a shortcut for:
+///
+/// ```ignore
+/// run_until(ctrl_c(), f)
+/// ```
+///
+/// After the first Ctrl-C is received, any next one terminates the process
+/// immediately (with exit code 130) until this function returns, so the
+/// application can't hang on shutdown.
+///
+/// Only available with `signal` feature enabled.
+pub fn run_until_ctrlc<F, E>(f: F) -> Result<(), E>
+    where F: FnOnce() -> Result<(), E>,
+{
+    let guard = ForceExit { armed: Rc::new(Cell::new(false)) };
+    let armed = guard.armed.clone();
+    run_until(ctrl_c().then(move |_| {
+        FORCE_EXIT.fetch_add(1, Ordering::SeqCst);
+        armed.set(true);
+        Ok::<(), ()>(())
+    }), f)
+}
+
+impl Drop for ForceExit {
+    fn drop(&mut self) {
+        if self.armed.get() {
+            FORCE_EXIT.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Abort the task when Ctrl-C (SIGINT) is received
+///
+/// This ties the lifetime of a task spawned by `spawn_abortable()` to the
+/// interrupt signal:
+///
+/// ```ignore
+/// abort_on_ctrlc(spawn_abortable(sync_files()));
+/// ```
+///
+/// Multiple handles can be registered, all of them are aborted on the same
+/// signal. Only available with `signal` feature enabled.
+///
+/// The waiting task is dropped with its loop, so after the loop exits
+/// Ctrl-C terminates the process again (with exit code 130):
+///
+/// ```
+/// # extern crate futures;
+/// # extern crate tk_easyloop;
+/// # use std::env;
+/// # use std::process::{self, Command};
+/// # use std::thread;
+/// # use std::time::Duration;
+/// use futures::future::empty;
+/// use tk_easyloop::{run, timeout, spawn_abortable, abort_on_ctrlc};
+///
+/// # #[cfg(unix)]
+/// # fn main() {
+/// # if env::var_os("TK_EASYLOOP_CTRLC_CHILD").is_none() {
+/// #     let status = Command::new(env::current_exe().unwrap())
+/// #         .env("TK_EASYLOOP_CTRLC_CHILD", "1")
+/// #         .status().unwrap();
+/// #     assert_eq!(status.code(), Some(130));
+/// #     return;
+/// # }
+/// run(|| {
+///     abort_on_ctrlc(spawn_abortable(empty::<(), ()>()));
+///     timeout(Duration::from_millis(10))
+/// }).unwrap();
+/// // nothing waits for Ctrl-C here, so it's not swallowed
+/// # Command::new("kill").arg("-INT").arg(process::id().to_string())
+/// #     .status().unwrap();
+/// # thread::sleep(Duration::new(10, 0));
+/// # }
+/// # #[cfg(not(unix))] fn main() {}
+/// ```
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`. Also see `ctrl_c()`.
+pub fn abort_on_ctrlc(handle: AbortHandle) {
+    spawn(ctrl_c().then(move |_| {
+        handle.abort();
+        Ok(())
+    }));
+}
@@ -0,0 +1,53 @@
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+
+thread_local! {
+    // the base instant is needed to round deadlines to the same grid
+    static SLACK: Cell<Option<(Instant, Duration)>> = const { Cell::new(None) };
+}
+
+/// Set the timer slack for the current thread
+///
+/// Timers created by `timeout()`, `timeout_at()`, `sleep()` and
+/// `interval()` afterwards get their deadlines rounded up to the next
+/// multiple of `dur`, so timers that are close to each other fire at
+/// the same instant and the loop wakes up less often. This is useful for
+/// daemons that care about power usage more than about precise timing.
+///
+/// Zero duration (the default) disables rounding.
+///
+/// This only affects timers created through this crate's helpers, not
+/// `Timeout::new` or `Interval::new` called directly. Also `interval_at()`
+/// is not affected, as it's used for explicit alignment.
+pub fn set_timer_slack(dur: Duration) {
+    SLACK.with(|s| {
+        if dur == Duration::new(0, 0) {
+            s.set(None);
+        } else {
+            s.set(Some((Instant::now(), dur)));
+        }
+    })
+}
+
+/// Returns the slack set by `set_timer_slack()`
+pub(crate) fn timer_slack() -> Duration {
+    SLACK.with(|s| s.get()).map(|(_, dur)| dur).unwrap_or(Duration::new(0, 0))
+}
+
+/// Returns `None` if deadline doesn't need rounding
+pub(crate) fn deadline(at: Instant) -> Option<Instant> {
+    let (base, slack) = SLACK.with(|s| s.get())?;
+    let since_base = at.saturating_duration_since(base).as_nanos();
+    let slack = slack.as_nanos();
+    let rounded = (since_base + slack - 1) / slack * slack;
+    Some(base + Duration::from_nanos(rounded as u64))
+}
+
+/// Returns `None` if period doesn't need rounding
+pub(crate) fn period(dur: Duration) -> Option<Duration> {
+    let (_, slack) = SLACK.with(|s| s.get())?;
+    let slack = slack.as_nanos();
+    let rounded = (dur.as_nanos().max(1) + slack - 1) / slack * slack;
+    Some(Duration::from_nanos(rounded as u64))
+}
@@ -0,0 +1,29 @@
+use futures::{Future, Async, Poll};
+use tokio_core::reactor::Timeout;
+
+
+/// A future returned by `sleep()`
+///
+/// Resolves to `()` when the duration elapses. Unlike `Timeout` it has
+/// `()` as an error type, so it composes with futures passed to `spawn()`.
+pub struct Sleep {
+    timeout: Timeout,
+}
+
+impl Sleep {
+    pub(crate) fn new(timeout: Timeout) -> Sleep {
+        Sleep { timeout }
+    }
+}
+
+impl Future for Sleep {
+    type Item = ();
+    type Error = ();
+    fn poll(&mut self) -> Poll<(), ()> {
+        match self.timeout.poll() {
+            Ok(Async::Ready(())) => Ok(Async::Ready(())),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(e) => panic!("timer failed: {}", e),
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+use std::time::{Duration, Instant};
+
+use futures::{Future, Poll};
+
+use driver::{loop_name, catch_panics};
+use panic::{panic_message, set_panicked_task};
+use {now, spawn};
+
+
+thread_local! {
+    static NEXT_TASK_ID: Cell<u64> = const { Cell::new(0) };
+    static TASKS: RefCell<BTreeMap<u64, (&'static str, Instant)>> =
+        const { RefCell::new(BTreeMap::new()) };
+}
+
+/// Information about a running task returned by `task_table()`
+#[derive(Debug, Clone)]
+pub struct TaskInfo {
+    /// Name of the task passed to `spawn_named()` or `spawn_detached()`
+    pub name: &'static str,
+    /// Time when the task was spawned
+    pub spawned_at: Instant,
+    /// Time since the task was spawned
+    pub age: Duration,
+}
+
+struct Named<F> {
+    id: u64,
+    name: &'static str,
+    future: F,
+}
+
+impl<F> Named<F> {
+    fn new(name: &'static str, future: F) -> Named<F> {
+        let id = NEXT_TASK_ID.with(|n| {
+            let id = n.get();
+            n.set(id + 1);
+            id
+        });
+        TASKS.with(|t| t.borrow_mut().insert(id, (name, now())));
+        Named { id, name, future }
+    }
+}
+
+impl<F: Future> Future for Named<F> {
+    type Item = F::Item;
+    type Error = F::Error;
+    fn poll(&mut self) -> Poll<F::Item, F::Error> {
+        let future = &mut self.future;
+        match catch_unwind(AssertUnwindSafe(|| future.poll())) {
+            Ok(result) => result,
+            Err(payload) => {
+                if catch_panics() {
+                    // reported once, with the name, by the catch handler
+                    set_panicked_task(self.name);
+                    resume_unwind(payload);
+                }
+                match loop_name() {
+                    Some(lp) => {
+                        eprintln!("tk-easyloop: task {:?} in loop {:?} \
+                            panicked: {}", self.name, lp,
+                            panic_message(&*payload));
+                    }
+                    None => {
+                        eprintln!("tk-easyloop: task {:?} panicked: {}",
+                            self.name, panic_message(&*payload));
+                    }
+                }
+                // the original payload is kept for `spawn_catch()` and
+                // `run_caught()`
+                resume_unwind(payload)
+            }
+        }
+    }
+}
+
+impl<F> Drop for Named<F> {
+    fn drop(&mut self) {
+        // registry might be already destroyed if the loop is dropped at
+        // thread exit
+        TASKS.try_with(|t| t.borrow_mut().remove(&self.id)).ok();
+    }
+}
+
+/// Spawn a named future to the current main loop
+///
+/// The name is visible in `running_tasks()` and `task_table()` until the
+/// future is finished (or dropped). If the future panics, the name is
+/// printed to stderr and the panic is propagated with the original
+/// payload. When the loop catches panics (`Loop::catch_panics()`), the
+/// panic is reported once by the hook set by `set_panic_hook()` instead,
+/// see `panicked_task()`.
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn spawn_named<F>(name: &'static str, f: F)
+    where F: Future<Item=(), Error=()> + 'static
+{
+    spawn(Named::new(name, f))
+}
+
+/// Spawn a named future to the current main loop and ignore its result
+///
+/// This is like `spawn_named()` but accepts any future, both its value and
+/// its error are discarded. The task is counted in `metrics().active_tasks`
+/// and is listed in `task_table()` with its name and start time until it's
+/// finished:
+///
+/// ```
+/// # extern crate futures;
+/// # extern crate tk_easyloop;
+/// use futures::future::empty;
+/// use tk_easyloop::{run, spawn_detached, task_table, metrics};
+///
+/// # fn main() {
+/// run(|| {
+///     spawn_detached("warm-up", empty::<(), ()>());
+///     assert_eq!(metrics().active_tasks, 1);
+///     let names: Vec<_> = task_table().iter().map(|t| t.name).collect();
+///     assert_eq!(names, ["warm-up"]);
+///     Ok::<(), ()>(())
+/// }).unwrap();
+/// # }
+/// ```
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn spawn_detached<F>(name: &'static str, f: F)
+    where F: Future + 'static
+{
+    spawn_named(name, f.then(|_| Ok(())))
+}
+
+/// Returns names of currently running tasks spawned by `spawn_named()`
+///
+/// Tasks are returned in the order they were spawned. Only tasks of
+/// the current thread are returned.
+pub fn running_tasks() -> Vec<&'static str> {
+    TASKS.with(|t| t.borrow().values().map(|&(name, _)| name).collect())
+}
+
+/// Returns information about currently running tasks spawned by
+/// `spawn_named()` or `spawn_detached()`
+///
+/// This is useful for debugging stuck loops, e.g. exposed on an admin
+/// endpoint:
+///
+/// ```ignore
+/// for task in task_table() {
+///     println!("{} running for {:?}", task.name, task.age);
+/// }
+/// ```
+///
+/// Tasks are returned in the order they were spawned. Only tasks of
+/// the current thread are returned.
+pub fn task_table() -> Vec<TaskInfo> {
+    let time = now();
+    TASKS.with(|t| {
+        t.borrow().values().map(|&(name, spawned_at)| TaskInfo {
+            name,
+            spawned_at,
+            age: time.saturating_duration_since(spawned_at),
+        }).collect()
+    })
+}
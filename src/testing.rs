@@ -0,0 +1,142 @@
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+use tokio_core::reactor::Core;
+use tokio_timer::clock::{self, Clock as TimerClock};
+
+use driver;
+
+
+thread_local! {
+    static CLOCK: Cell<Clock> = const { Cell::new(Clock {
+        paused: None,
+        offset: Duration::from_secs(0),
+    }) };
+}
+
+#[derive(Clone, Copy)]
+struct Clock {
+    paused: Option<Instant>,
+    // how far the clock got ahead of real time while it was paused
+    offset: Duration,
+}
+
+struct VirtualNow;
+
+impl clock::Now for VirtualNow {
+    fn now(&self) -> Instant {
+        let clock = CLOCK.with(|c| c.get());
+        match clock.paused {
+            // timer rounds deadlines up to a millisecond, so a timer set
+            // for exactly the time reached by `advance()` would never fire
+            Some(time) => time + timer_slop(),
+            None => Instant::now() + clock.offset,
+        }
+    }
+}
+
+fn timer_slop() -> Duration {
+    Duration::from_millis(1) - Duration::new(0, 1)
+}
+
+/// Pause the clock of the loops on the current thread
+///
+/// After this call the time doesn't pass for all the timers of this crate:
+/// `sleep()`, `timeout()`, `interval()` and everything built on them
+/// (`deadline()`, `with_timeout()`, `TimerWheel`, ...), and for `now()`.
+/// The clock only moves forward by `advance()`. This allows testing
+/// timeout and backoff logic without real sleeps:
+///
+/// ```
+/// # extern crate futures;
+/// # extern crate tk_easyloop;
+/// # use std::time::{Duration, Instant};
+/// use futures::{Future, Stream};
+/// use tk_easyloop::{run, sleep, timeout, interval, now};
+/// use tk_easyloop::{pause, advance, resume};
+///
+/// # fn main() {
+/// let started = Instant::now();
+/// run(|| {
+///     pause();
+///     let paused_at = now();
+///     let retry = sleep(Duration::new(30, 0));
+///     let expire = timeout(Duration::new(60, 0));
+///     let ticks = interval(Duration::new(10, 0)).take(3).collect();
+///     advance(Duration::new(60, 0));
+///     retry.join3(expire.map_err(|_| ()), ticks.map_err(|_| ()))
+///     .map(move |((), (), ticks)| {
+///         assert_eq!(ticks.len(), 3);
+///         assert!(now() - paused_at >= Duration::new(60, 0));
+///         resume();
+///     })
+/// }).unwrap();
+/// assert!(started.elapsed() < Duration::new(30, 0));
+/// # }
+/// ```
+///
+/// The clock is used by the loops created on this thread (by `run()` and
+/// friends) while the `testing` feature is enabled. Note that timers
+/// created by `Timeout::new` directly compute their deadline from the
+/// real `Instant::now()`, use `timeout()` or `EasyHandle` instead.
+///
+/// Does nothing if the clock is already paused.
+pub fn pause() {
+    CLOCK.with(|c| {
+        let mut clock = c.get();
+        if clock.paused.is_none() {
+            clock.paused = Some(Instant::now() + clock.offset);
+            c.set(clock);
+        }
+    });
+    driver::clear_now();
+}
+
+/// Move the paused clock forward
+///
+/// Timers which are elapsed after this are fired on the next loop
+/// iteration.
+///
+/// # Panics
+///
+/// If the clock is not paused by `pause()`
+pub fn advance(dur: Duration) {
+    CLOCK.with(|c| {
+        let mut clock = c.get();
+        let time = clock.paused
+            .expect("tk-easyloop: clock advanced when it isn't paused");
+        clock.paused = Some(time + dur);
+        c.set(clock);
+    });
+    driver::clear_now();
+}
+
+/// Let the clock run again
+///
+/// The time continues from the virtual time reached by `advance()`, so
+/// the clock never goes backwards and pending timers keep their deadlines.
+/// Does nothing if the clock isn't paused.
+pub fn resume() {
+    CLOCK.with(|c| {
+        let mut clock = c.get();
+        if let Some(time) = clock.paused.take() {
+            // don't let the time seen by the timer go backwards
+            let time = time + timer_slop();
+            clock.offset = time.saturating_duration_since(Instant::now());
+            c.set(clock);
+        }
+    });
+    driver::clear_now();
+}
+
+/// Returns current time of the (possibly paused) clock
+pub(crate) fn clock_now() -> Instant {
+    let clock = CLOCK.with(|c| c.get());
+    clock.paused.unwrap_or_else(|| Instant::now() + clock.offset)
+}
+
+/// Creates a loop which timer is driven by the clock of this module
+pub(crate) fn new_core() -> Core {
+    let _guard = clock::set_default(&TimerClock::new_with_now(VirtualNow));
+    Core::new().expect("create loop")
+}
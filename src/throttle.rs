@@ -0,0 +1,91 @@
+use std::time::{Duration, Instant};
+
+use futures::{Future, Stream, Async, Poll};
+use tokio_core::reactor::Timeout;
+
+use driver::clock_now;
+use timeout_at;
+
+
+/// A stream returned by `throttle()`
+pub struct Throttle<S> {
+    stream: S,
+    min_interval: Duration,
+    next_allowed: Option<Instant>,
+    timer: Option<Timeout>,
+}
+
+/// Create a stream which yields items of `s` not more often than
+/// `min_interval`
+///
+/// When items arrive too quickly the stream waits on a timer before
+/// polling the source stream (so the source is backpressured rather than
+/// buffered). If the source is slower than the limit, items pass through
+/// without delay:
+///
+/// ```ignore
+/// spawn_stream(throttle(updates, Duration::from_millis(100)), |update| {
+///     redraw(update);
+///     Ok(())
+/// })
+/// ```
+///
+/// Items are yielded in order, and the timer is restarted after each item:
+///
+/// ```
+/// # extern crate futures;
+/// # extern crate tk_easyloop;
+/// # use std::time::{Duration, Instant};
+/// # use futures::Stream;
+/// # use futures::stream::iter_ok;
+/// # use tk_easyloop::{run, throttle};
+/// # fn main() {
+/// let started = Instant::now();
+/// let items = run(|| {
+///     throttle(iter_ok::<_, ()>(0..4), Duration::from_millis(30)).collect()
+/// }).unwrap();
+/// assert_eq!(items, [0, 1, 2, 3]);
+/// // the first item is not delayed
+/// assert!(started.elapsed() >= Duration::from_millis(90));
+/// # }
+/// ```
+///
+/// # Panics
+///
+/// The stream panics if it's polled when no loop is running. Also the
+/// stream panics if timer fails (which never happens in current tokio)
+pub fn throttle<S: Stream>(s: S, min_interval: Duration) -> Throttle<S> {
+    Throttle {
+        stream: s,
+        min_interval,
+        next_allowed: None,
+        timer: None,
+    }
+}
+
+impl<S: Stream> Stream for Throttle<S> {
+    type Item = S::Item;
+    type Error = S::Error;
+    fn poll(&mut self) -> Poll<Option<S::Item>, S::Error> {
+        if let Some(at) = self.next_allowed {
+            if clock_now() < at {
+                let timer = self.timer.get_or_insert_with(|| timeout_at(at));
+                match timer.poll() {
+                    Ok(Async::Ready(())) => {}
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Err(e) => panic!("timer failed: {}", e),
+                }
+            }
+            self.next_allowed = None;
+            self.timer = None;
+        }
+        match self.stream.poll()? {
+            Async::Ready(Some(item)) => {
+                self.next_allowed = Some(clock_now() + self.min_interval);
+                Ok(Async::Ready(Some(item)))
+            }
+            Async::Ready(None) => Ok(Async::Ready(None)),
+            Async::NotReady => Ok(Async::NotReady),
+        }
+    }
+}
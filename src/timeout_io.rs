@@ -0,0 +1,127 @@
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+use futures::{Future, Async, Poll};
+use tokio_core::reactor::Timeout;
+use tokio_io::{AsyncRead, AsyncWrite};
+
+use timeout;
+
+
+/// A wrapper around `AsyncRead`/`AsyncWrite` which fails operations that
+/// block for too long, see `timeout_io()`
+pub struct TimeoutIo<T> {
+    io: T,
+    read: Timer,
+    write: Timer,
+}
+
+struct Timer {
+    dur: Option<Duration>,
+    timeout: Option<Timeout>,
+}
+
+/// Wrap an IO object to apply a timeout to each read and write operation
+///
+/// When a read (or a write) can't make progress for `dur`, it fails with
+/// `io::ErrorKind::TimedOut` error. Until then `WouldBlock` is returned
+/// as usual, so the object works with all the `tokio_io` combinators:
+///
+/// ```ignore
+/// let conn = timeout_io(conn, Duration::new(30, 0));
+/// tokio_io::io::read_exact(conn, [0u8; 4])
+/// .map_err(|e| if e.kind() == io::ErrorKind::TimedOut {
+///     println!("Peer is not responding");
+/// })
+/// ```
+///
+/// Timer is restarted each time the operation makes progress, so this
+/// limits the time peer is silent rather than the time of the whole
+/// transfer. Use `read_timeout()` and `write_timeout()` for different
+/// limits for each direction.
+///
+/// Note: timers are created on the current loop when the operation first
+/// blocks, so the object must be used from the inside of `run()`.
+pub fn timeout_io<T>(io: T, dur: Duration) -> TimeoutIo<T> {
+    TimeoutIo {
+        io,
+        read: Timer { dur: Some(dur), timeout: None },
+        write: Timer { dur: Some(dur), timeout: None },
+    }
+}
+
+impl<T> TimeoutIo<T> {
+    /// Set timeout of read operations (`None` for no timeout)
+    pub fn read_timeout(mut self, dur: Option<Duration>) -> TimeoutIo<T> {
+        self.read = Timer { dur, timeout: None };
+        self
+    }
+    /// Set timeout of write operations (`None` for no timeout)
+    pub fn write_timeout(mut self, dur: Option<Duration>) -> TimeoutIo<T> {
+        self.write = Timer { dur, timeout: None };
+        self
+    }
+    /// Returns a reference to the underlying IO object
+    pub fn get_ref(&self) -> &T {
+        &self.io
+    }
+    /// Returns a mutable reference to the underlying IO object
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.io
+    }
+    /// Returns the underlying IO object
+    pub fn into_inner(self) -> T {
+        self.io
+    }
+}
+
+impl Timer {
+    fn check<R>(&mut self, result: io::Result<R>) -> io::Result<R> {
+        match result {
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            result => {
+                self.timeout = None;
+                return result;
+            }
+        }
+        let dur = match self.dur {
+            Some(dur) => dur,
+            None => return result,
+        };
+        let elapsed = self.timeout.get_or_insert_with(|| timeout(dur)).poll()
+            .expect("timer failed");
+        match elapsed {
+            Async::Ready(()) => {
+                self.timeout = None;
+                Err(io::ErrorKind::TimedOut.into())
+            }
+            Async::NotReady => result,
+        }
+    }
+}
+
+impl<T: Read> Read for TimeoutIo<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let result = self.io.read(buf);
+        self.read.check(result)
+    }
+}
+
+impl<T: Write> Write for TimeoutIo<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let result = self.io.write(buf);
+        self.write.check(result)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        let result = self.io.flush();
+        self.write.check(result)
+    }
+}
+
+impl<T: AsyncRead> AsyncRead for TimeoutIo<T> {}
+
+impl<T: AsyncWrite> AsyncWrite for TimeoutIo<T> {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.io.shutdown()
+    }
+}
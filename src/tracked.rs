@@ -0,0 +1,150 @@
+use std::cell::RefCell;
+use std::time::Duration;
+
+use futures::{self, Future, IntoFuture, Async, Poll};
+use futures::task::{self, Task};
+
+use driver::{drive, new_core, Config};
+use {spawn, with_timeout};
+
+
+thread_local! {
+    static TRACKED: RefCell<Tracker> = const { RefCell::new(Tracker {
+        count: 0,
+        waiters: Vec::new(),
+    }) };
+}
+
+struct Tracker {
+    count: usize,
+    waiters: Vec<Task>,
+}
+
+struct Tracked<F> {
+    future: F,
+}
+
+/// Resolves when there are no tracked tasks left
+struct Drained;
+
+impl<F: Future> Future for Tracked<F> {
+    type Item = F::Item;
+    type Error = F::Error;
+    fn poll(&mut self) -> Poll<F::Item, F::Error> {
+        self.future.poll()
+    }
+}
+
+impl<F> Drop for Tracked<F> {
+    fn drop(&mut self) {
+        // tracker might be already destroyed if the loop is dropped at
+        // thread exit
+        TRACKED.try_with(|t| {
+            let mut t = t.borrow_mut();
+            t.count -= 1;
+            if t.count == 0 {
+                for task in t.waiters.drain(..) {
+                    task.notify();
+                }
+            }
+        }).ok();
+    }
+}
+
+impl Future for Drained {
+    type Item = ();
+    type Error = ();
+    fn poll(&mut self) -> Poll<(), ()> {
+        TRACKED.with(|t| {
+            let mut t = t.borrow_mut();
+            if t.count == 0 {
+                Ok(Async::Ready(()))
+            } else {
+                t.waiters.push(task::current());
+                Ok(Async::NotReady)
+            }
+        })
+    }
+}
+
+/// Spawn a future to the current main loop and track it for draining
+///
+/// Loops started by `run_drained()` and `run_joined()` wait for tracked
+/// futures to finish before exiting. Only the futures spawned by this
+/// function are tracked.
+///
+/// Note: the count of tracked futures is kept per thread, not per loop.
+/// So futures which outlive their loop (e.g. ones left in the core of
+/// `run_cached()`) are also waited for by the next loop on the thread.
+///
+/// # Panics
+///
+/// This function panics if there is no currently running loop (i.e. this
+/// function is not running from the inside of `run()`.
+pub fn spawn_tracked<F>(f: F)
+    where F: Future<Item=(), Error=()> + 'static
+{
+    TRACKED.with(|t| t.borrow_mut().count += 1);
+    spawn(Tracked { future: f });
+}
+
+/// Returns number of tasks spawned by `spawn_tracked()` which are not
+/// finished yet
+///
+/// The number is per thread, so it includes tasks of all the loops of the
+/// current thread which are not dropped yet.
+pub fn tracked_tasks() -> usize {
+    TRACKED.with(|t| t.borrow().count)
+}
+
+/// Run the main loop until `shutdown` resolves, then wait for the tracked
+/// tasks to finish
+///
+/// This is like `run_until()`, but when `shutdown` resolves it waits
+/// up to `grace` time for all the tasks spawned by `spawn_tracked()` to
+/// complete. This gives servers the "stop accepting, finish what's in
+/// flight, then exit" semantics (stopping accepting connections is up
+/// to the application, though).
+///
+/// Returns number of tracked tasks that were still running when the
+/// grace time elapsed (so `Ok(0)` means clean shutdown). Those tasks are
+/// dropped.
+pub fn run_drained<S, F, E>(shutdown: S, grace: Duration, f: F)
+    -> Result<usize, E>
+    where S: Future,
+          F: FnOnce() -> Result<(), E>,
+{
+    let mut lp = new_core();
+    drive(&mut lp, &Config::default(), futures::lazy(f)
+        .and_then(|()| shutdown.then(|_| Ok(())))
+        .and_then(move |()| {
+            with_timeout(Drained, grace).then(|_| Ok(tracked_tasks()))
+        }))
+}
+
+/// Run the main loop like `run()`, then wait for all the tracked tasks to
+/// finish
+///
+/// When the future returned by `f` resolves, the loop continues running
+/// until all the tasks spawned by `spawn_tracked()` are complete, and then
+/// returns the result of the future. Untracked tasks are abandoned as
+/// usual:
+///
+/// ```ignore
+/// run_joined(|| {
+///     for file in files {
+///         spawn_tracked(upload(file));
+///     }
+///     Ok::<(), ()>(())
+/// })
+/// ```
+///
+/// If the future fails, the error is returned immediately without waiting.
+pub fn run_joined<F, R>(f: F) -> Result<R::Item, R::Error>
+    where F: FnOnce() -> R,
+          R: IntoFuture,
+{
+    let mut lp = new_core();
+    drive(&mut lp, &Config::default(), futures::lazy(f)
+        .and_then(|value| Drained.then(move |_| Ok(value))))
+}
@@ -0,0 +1,83 @@
+use std::time::Duration;
+
+use futures::{Future, Stream, Async, Poll};
+use tokio_core::reactor::{Interval, Timeout};
+
+use {interval, timeout};
+
+
+/// A future returned by `wait_for()`
+pub struct WaitFor<F> {
+    condition: F,
+    interval: Interval,
+    timeout: Option<Timeout>,
+    checked: bool,
+}
+
+/// Create a future which resolves when the condition becomes true
+///
+/// The condition is checked when the future is polled first time and then
+/// on every tick of `poll_interval`. This is useful for waiting on
+/// external events which don't have a notification mechanism:
+///
+/// ```ignore
+/// wait_for(Duration::from_millis(100), || path.exists())
+///     .timeout(Duration::new(10, 0))
+///     .map_err(|()| println!("File didn't appear in 10 seconds"))
+/// ```
+///
+/// # Panics
+///
+/// When no loop is running (`handle()` panics). Also the future panics if
+/// timer fails (which never happens in current tokio)
+pub fn wait_for<F>(poll_interval: Duration, f: F) -> WaitFor<F>
+    where F: FnMut() -> bool + 'static
+{
+    WaitFor {
+        condition: f,
+        interval: interval(poll_interval),
+        timeout: None,
+        checked: false,
+    }
+}
+
+impl<F> WaitFor<F> {
+    /// Fail the future with `Err(())` if the condition is not true
+    /// within the duration
+    pub fn timeout(mut self, dur: Duration) -> WaitFor<F> {
+        self.timeout = Some(timeout(dur));
+        self
+    }
+}
+
+impl<F: FnMut() -> bool> Future for WaitFor<F> {
+    type Item = ();
+    type Error = ();
+    fn poll(&mut self) -> Poll<(), ()> {
+        if !self.checked {
+            self.checked = true;
+            if (self.condition)() {
+                return Ok(Async::Ready(()));
+            }
+        }
+        loop {
+            match self.interval.poll() {
+                Ok(Async::Ready(_)) => {
+                    if (self.condition)() {
+                        return Ok(Async::Ready(()));
+                    }
+                }
+                Ok(Async::NotReady) => break,
+                Err(e) => panic!("timer failed: {}", e),
+            }
+        }
+        if let Some(ref mut timeout) = self.timeout {
+            match timeout.poll() {
+                Ok(Async::Ready(())) => return Err(()),
+                Ok(Async::NotReady) => {}
+                Err(e) => panic!("timer failed: {}", e),
+            }
+        }
+        Ok(Async::NotReady)
+    }
+}
@@ -0,0 +1,243 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::{Rc, Weak};
+use std::time::{Duration, Instant};
+
+use futures::{Future, Stream, Async, Poll};
+use futures::task::{self, Task};
+use tokio_core::reactor::Interval;
+
+use driver::clock_now;
+use {interval, now, spawn};
+
+
+/// A coarse-grained scheduler of many timeouts
+///
+/// All timeouts created by the wheel are served by a single `interval()`
+/// ticking with the specified granularity. So the timeouts are cheap to
+/// create and reset, but may fire up to one `granularity` later than
+/// requested. This is useful for things like per-connection idle timers,
+/// where there are lots of timers and precision is not important.
+///
+/// The wheel is cheap to clone, all clones refer to the same wheel. The
+/// interval is stopped when the wheel and all its timeouts are dropped.
+///
+/// ```
+/// # extern crate futures;
+/// # extern crate tk_easyloop;
+/// # use std::cell::RefCell;
+/// # use std::rc::Rc;
+/// # use std::time::Duration;
+/// # use futures::Future;
+/// # use tk_easyloop::{run, spawn, timeout, TimerWheel};
+/// # fn main() {
+/// let fired = Rc::new(RefCell::new(Vec::new()));
+/// run(|| {
+///     let wheel = TimerWheel::new(Duration::from_millis(10));
+///     let a = wheel.timeout(Duration::from_millis(40));
+///     let b = wheel.timeout(Duration::from_millis(20));
+///     let mut c = wheel.timeout(Duration::from_millis(20));
+///     c.reset(Duration::from_millis(60));
+///     for (name, t) in vec![("a", a), ("b", b), ("c", c)] {
+///         let fired = fired.clone();
+///         spawn(t.map(move |()| fired.borrow_mut().push(name)));
+///     }
+///     timeout(Duration::from_millis(200))
+/// }).unwrap();
+/// assert_eq!(*fired.borrow(), ["b", "a", "c"]);
+/// # }
+/// ```
+///
+/// The timeouts never fire earlier than requested:
+///
+/// ```
+/// # extern crate futures;
+/// # extern crate tk_easyloop;
+/// # use std::time::{Duration, Instant};
+/// # use futures::Future;
+/// # use futures::future::join_all;
+/// # use tk_easyloop::{run, TimerWheel};
+/// # fn main() {
+/// run(|| {
+///     let wheel = TimerWheel::new(Duration::from_millis(10));
+///     join_all((1..8).map(|i| {
+///         let dur = Duration::from_millis(i * 7);
+///         let started = Instant::now();
+///         wheel.timeout(dur).map(move |()| {
+///             assert!(started.elapsed() >= dur);
+///         })
+///     }).collect::<Vec<_>>())
+/// }).unwrap();
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct TimerWheel {
+    inner: Rc<RefCell<Inner>>,
+}
+
+/// A timeout created by `TimerWheel::timeout()`
+///
+/// Resolves to `()` when the timeout expires. Dropping the value cancels
+/// the timeout.
+pub struct WheelTimeout {
+    id: u64,
+    inner: Rc<RefCell<Inner>>,
+}
+
+struct Entry {
+    tick: u64,
+    fired: bool,
+    task: Option<Task>,
+}
+
+struct Inner {
+    start: Instant,
+    granularity: Duration,
+    next_id: u64,
+    entries: HashMap<u64, Entry>,
+    // entries are removed from the buckets lazily, so ids here may refer
+    // to cancelled entries or to the ones that were moved to another bucket
+    buckets: BTreeMap<u64, Vec<u64>>,
+}
+
+struct Driver {
+    interval: Interval,
+    inner: Weak<RefCell<Inner>>,
+}
+
+impl Inner {
+    fn tick_for(&self, at: Instant) -> u64 {
+        let passed = at.duration_since(self.start).as_nanos();
+        let granularity = self.granularity.as_nanos();
+        ((passed + granularity - 1) / granularity) as u64
+    }
+    fn current_tick(&self, now: Instant) -> u64 {
+        // only the ticks which are fully passed
+        let passed = now.duration_since(self.start).as_nanos();
+        (passed / self.granularity.as_nanos()) as u64
+    }
+    fn schedule(&mut self, id: u64, dur: Duration) {
+        // not the cached `now()`, as the lagging time would make the
+        // timeout fire early
+        let tick = self.tick_for(clock_now() + dur);
+        self.buckets.entry(tick).or_default().push(id);
+        let entry = self.entries.get_mut(&id).expect("entry exists");
+        entry.tick = tick;
+        entry.fired = false;
+    }
+    fn expire(&mut self, now: Instant) {
+        let current = self.current_tick(now);
+        let pending = self.buckets.split_off(&(current + 1));
+        let expired = ::std::mem::replace(&mut self.buckets, pending);
+        for (tick, ids) in expired {
+            for id in ids {
+                if let Some(entry) = self.entries.get_mut(&id) {
+                    if entry.tick == tick && !entry.fired {
+                        entry.fired = true;
+                        if let Some(task) = entry.task.take() {
+                            task.notify();
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl TimerWheel {
+    /// Create a new wheel with the specified granularity
+    ///
+    /// # Panics
+    ///
+    /// When no loop is running (`handle()` panics) or if `granularity` is
+    /// zero.
+    pub fn new(granularity: Duration) -> TimerWheel {
+        assert!(granularity > Duration::new(0, 0),
+            "timer wheel granularity must be non-zero");
+        let inner = Rc::new(RefCell::new(Inner {
+            start: now(),
+            granularity,
+            next_id: 0,
+            entries: HashMap::new(),
+            buckets: BTreeMap::new(),
+        }));
+        spawn(Driver {
+            interval: interval(granularity),
+            inner: Rc::downgrade(&inner),
+        });
+        TimerWheel { inner }
+    }
+    /// Create a timeout that expires after `dur` (rounded up to the
+    /// granularity of the wheel)
+    pub fn timeout(&self, dur: Duration) -> WheelTimeout {
+        let mut inner = self.inner.borrow_mut();
+        let id = inner.next_id;
+        inner.next_id += 1;
+        inner.entries.insert(id, Entry { tick: 0, fired: false, task: None });
+        inner.schedule(id, dur);
+        WheelTimeout { id, inner: self.inner.clone() }
+    }
+}
+
+impl WheelTimeout {
+    /// Reschedule the timeout to expire after `dur` from now
+    ///
+    /// This works even if timeout has already expired.
+    pub fn reset(&mut self, dur: Duration) {
+        self.inner.borrow_mut().schedule(self.id, dur);
+    }
+    /// Cancel the timeout
+    ///
+    /// Cancelled timeout never resolves. This is the same as dropping the
+    /// timeout, but allows to keep the value in a structure.
+    pub fn cancel(&mut self) {
+        let mut inner = self.inner.borrow_mut();
+        if let Some(entry) = inner.entries.get_mut(&self.id) {
+            // entry will be skipped by `expire` because ticks never match
+            entry.tick = u64::MAX;
+            entry.task = None;
+        }
+    }
+}
+
+impl Future for WheelTimeout {
+    type Item = ();
+    type Error = ();
+    fn poll(&mut self) -> Poll<(), ()> {
+        let mut inner = self.inner.borrow_mut();
+        let entry = inner.entries.get_mut(&self.id).expect("entry exists");
+        if entry.fired {
+            Ok(Async::Ready(()))
+        } else {
+            entry.task = Some(task::current());
+            Ok(Async::NotReady)
+        }
+    }
+}
+
+impl Drop for WheelTimeout {
+    fn drop(&mut self) {
+        self.inner.borrow_mut().entries.remove(&self.id);
+    }
+}
+
+impl Future for Driver {
+    type Item = ();
+    type Error = ();
+    fn poll(&mut self) -> Poll<(), ()> {
+        loop {
+            let inner = match self.inner.upgrade() {
+                Some(inner) => inner,
+                None => return Ok(Async::Ready(())),
+            };
+            match self.interval.poll() {
+                Ok(Async::Ready(Some(()))) => {
+                    inner.borrow_mut().expire(now());
+                }
+                Ok(Async::Ready(None)) => return Ok(Async::Ready(())),
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Err(e) => panic!("timer failed: {}", e),
+            }
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use futures::{Future, Async, Poll};
+use futures::task;
+
+
+/// A future returned by `yield_now()`
+pub struct YieldNow {
+    yielded: bool,
+}
+
+/// Create a future that resolves at the next loop iteration
+///
+/// On the first poll the future schedules a wakeup of the current task
+/// and returns `NotReady`, on the next poll it resolves. This allows
+/// other tasks to run in the meantime, so it's useful to break up long
+/// CPU-bound work in a spawned future:
+///
+/// ```ignore
+/// spawn(stream::iter_ok(0..1000000).for_each(|i| {
+///     process(i);
+///     if i % 1000 == 0 {
+///         Box::new(yield_now()) as Box<Future<Item=(), Error=()>>
+///     } else {
+///         Box::new(future::ok(()))
+///     }
+/// }));
+/// ```
+///
+/// Note: the future must be polled from within a task (i.e. inside a
+/// running loop).
+pub fn yield_now() -> YieldNow {
+    YieldNow { yielded: false }
+}
+
+impl Future for YieldNow {
+    type Item = ();
+    type Error = ();
+    fn poll(&mut self) -> Poll<(), ()> {
+        if self.yielded {
+            Ok(Async::Ready(()))
+        } else {
+            self.yielded = true;
+            task::current().notify();
+            Ok(Async::NotReady)
+        }
+    }
+}